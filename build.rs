@@ -4,14 +4,56 @@ extern crate shlex;
 use bindgen::builder;
 use std::env;
 use std::fmt::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use serde_json::json;
 
-fn main() {
-    let cc;
-    let mut cflags;
+#[path = "build/api_diff.rs"]
+mod api_diff;
+#[path = "build/bindgen_callbacks.rs"]
+mod bindgen_callbacks;
+#[path = "build/bindgen_enums.rs"]
+mod bindgen_enums;
+#[path = "build/bindgen_flags.rs"]
+mod bindgen_flags;
+#[path = "build/bindgen_items.rs"]
+mod bindgen_items;
+#[path = "build/bindgen_passes.rs"]
+mod bindgen_passes;
+#[path = "build/c2rust_cache.rs"]
+mod c2rust_cache;
+#[path = "build/c2rust_passes.rs"]
+mod c2rust_passes;
+#[path = "build/c2rust_probe.rs"]
+mod c2rust_probe;
+#[path = "build/c_types.rs"]
+mod c_types;
+#[path = "build/cflags_parts.rs"]
+mod cflags_parts;
+#[path = "build/errno.rs"]
+mod errno;
+#[path = "build/external_modules.rs"]
+mod external_modules;
+#[path = "build/pregenerated.rs"]
+mod pregenerated;
+#[cfg(feature = "riot-rs")]
+#[path = "build/riot_build_info.rs"]
+mod riot_build_info;
+#[path = "build/riot_defines.rs"]
+mod riot_defines;
+#[path = "build/riot_features.rs"]
+mod riot_features;
+#[path = "build/riot_version.rs"]
+mod riot_version;
+#[path = "build/rust_idents.rs"]
+mod rust_idents;
+#[path = "build/target_check.rs"]
+mod target_check;
 
+/// Number of lines of an API difference report shown as warnings
+const API_DIFF_WARNING_LINES: usize = 20;
+
+fn main() {
     #[cfg(not(feature = "riot-rs"))]
     if env::var("BUILDING_RIOT_RS").is_ok() {
         println!("");
@@ -23,254 +65,167 @@ fn main() {
         std::process::exit(1);
     }
 
-    #[cfg(not(feature = "riot-rs"))]
-    let compile_commands_json = "RIOT_COMPILE_COMMANDS_JSON";
-    #[cfg(feature = "riot-rs")]
-    let compile_commands_json = "DEP_RIOT_BUILD_COMPILE_COMMANDS_JSON";
-
-    println!("cargo:rerun-if-env-changed=BUILDING_RIOT_RS");
-    println!("cargo:rerun-if-env-changed=RIOT_CC");
-    println!("cargo:rerun-if-env-changed=RIOT_CFLAGS");
-    println!("cargo:rerun-if-env-changed={}", &compile_commands_json);
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
 
-    if let Ok(commands_json) = env::var(compile_commands_json) {
-        println!("cargo:rerun-if-changed={}", commands_json);
-        let commands_file = std::fs::File::open(&commands_json)
-            .expect(&format!("Failed to open {}", &commands_json));
+    // Under riot-rs, variables of the RIOT build are read from there; see the riot_build_info
+    // module for details
+    #[cfg(feature = "riot-rs")]
+    let build_info = riot_build_info::BuildInfo::load();
 
-        #[derive(Debug, serde::Deserialize)]
-        struct Entry {
-            arguments: Vec<String>,
+    let usemodule = || {
+        #[cfg(not(feature = "riot-rs"))]
+        {
+            println!("cargo:rerun-if-env-changed=RIOT_USEMODULE");
+            // We tolerate the absence. Older versions of riot-wrappers would then fail to
+            // enable modules, but newer versions just work without it (and would need a dummy
+            // variable passed in otherwise). On the long run, this is going away anyway.
+            env::var("RIOT_USEMODULE").unwrap_or_default()
         }
-        let parsed: Vec<Entry> = serde_json::from_reader(commands_file)
-            .expect(&format!("Failed to parse {}", &compile_commands_json));
-
-        // We need to find a consensus list -- otherwise single modules like stdio_uart that
-        // defines anything odd for its own purpose can throw things off. (It's not like the actual
-        // ABI compatibility should suffer from them, for any flags like enum packing need to be
-        // the same systemwide anyway for things to to go very wrong) -- but at any rate, finding
-        // some consensus is to some extent necessary here).
-        //
-        // This is relatively brittle, but still better than the previous approach of just taking
-        // the first entry.
-        //
-        // A good long-term solution might be to take CFLAGS as the build system produces them, but
-        // pass them through the LLVMization process of create_compile_commands without actually
-        // turning them into compile commands.
-        let mut consensus_cc: Option<&str> = None;
-        let mut consensus_cflag_groups: Option<Vec<Vec<&str>>> = None;
-        for entry in parsed.iter() {
-            if let Some(consensus_cc) = consensus_cc.as_ref() {
-                assert!(consensus_cc == &entry.arguments[0])
-            } else {
-                consensus_cc = Some(&entry.arguments[0]);
-            }
-            let arg_iter = entry.arguments[1..]
-                .iter()
-                .map(|s| s.as_str())
-                // Anything after -c is not CFLAGS but concrete input/output stuff.
-                .take_while(|&s| s != "-c" && s != "-MQ");
-            // Heuristically grouping them to drop different arguments as whole group
-            let mut cflag_groups = vec![];
-            for mut arg in arg_iter {
-                if arg.starts_with("-I") {
-                    // -I arguments are given inconsistently with and without trailing slashes;
-                    // removing them keeps them from being pruned from the consensus set
-                    arg = arg.trim_end_matches('/');
-                }
-                if arg.starts_with('-') {
-                    cflag_groups.push(vec![arg]);
-                } else {
-                    cflag_groups
-                        .last_mut()
-                        .expect("CFLAG options all start with a dash")
-                        .push(arg);
-                }
-            }
-            if let Some(consensus_cflag_groups) = consensus_cflag_groups.as_mut() {
-                if &cflag_groups != consensus_cflag_groups {
-                    // consensus is in a good ordering, so we'll just strip it down
-                    *consensus_cflag_groups = consensus_cflag_groups
-                        .drain(..)
-                        .filter(|i| {
-                            let mut keep = cflag_groups.contains(i);
-                            // USEMODULE_INCLUDES are sometimes not in all of the entries; see note
-                            // on brittleness above.
-                            keep |= i[0].starts_with("-I");
-                            // Left as multiple lines to ease hooking in with debug statements when
-                            // something goes wrong again...
-                            keep
-                        })
-                        .collect();
-                    // Hot-fixing the merging algorithm to even work when an (always to be kept) -I
-                    // is not in the initial set
-                    for group in cflag_groups.drain(..) {
-                        if group[0].starts_with("-I") {
-                            if !consensus_cflag_groups.contains(&group) {
-                                consensus_cflag_groups.push(group);
-                            }
-                        }
-                    }
-                }
-            } else {
-                consensus_cflag_groups = Some(cflag_groups);
-            }
+        #[cfg(feature = "riot-rs")]
+        {
+            build_info.get("USEMODULE")
         }
-        cc = consensus_cc
-            .expect("Entries are present in compile_commands.json")
-            .to_string();
-        cflags = shlex::try_join(consensus_cflag_groups.unwrap().iter().flatten().map(|s| *s))
-            .expect("Input is not expected to contain NUL characters");
-
-        let usemodule = {
-            #[cfg(not(feature = "riot-rs"))]
-            {
-                println!("cargo:rerun-if-env-changed=RIOT_USEMODULE");
-                // We tolerate the absence. Older versions of riot-wrappers would then fail to
-                // enable modules, but newer versions just work without it (and would need a dummy
-                // variable passed in otherwise). On the long run, this is going away anyway.
-                env::var("RIOT_USEMODULE").unwrap_or_default()
-            }
-            #[cfg(feature = "riot-rs")]
-            {
-                println!("cargo:rerun-if-env-changed=DEP_RIOT_BUILD_DIR");
-                let riot_builddir =
-                    env::var("DEP_RIOT_BUILD_DIR").expect("DEP_RIOT_BUILD_DIR unset?");
-                get_riot_var(&riot_builddir, "USEMODULE")
-            }
-        };
+    };
+    let riot_config = read_cc_cflags(usemodule);
 
-        for m in usemodule.split(" ") {
-            // Hack around https://github.com/RIOT-OS/RIOT/pull/16129#issuecomment-805810090
-            write!(
-                cflags,
-                " -DMODULE_{}",
-                m.to_uppercase()
-                    // avoid producing MODULE_BOARDS_COMMON_SAMDX1-ARDUINO-BOOTLOADER
-                    .replace('-', "_")
-            )
-            .unwrap();
-        }
-    } else {
-        cc = env::var("RIOT_CC")
-            .expect("Please pass in RIOT_CC; see README.md for details.")
-            .clone();
-        cflags = env::var("RIOT_CFLAGS")
-            .expect("Please pass in RIOT_CFLAGS; see README.md for details.");
+    // Bindings generated earlier can stand in for the whole generation; see the pregenerated
+    // module for details
+    if let Some(dir) = pregenerated::configured_dir() {
+        let config_hash = riot_config.as_ref().map(|(cc, cflags)| {
+            let split = shlex::split(cflags).expect("Odd shell escaping in RIOT_CFLAGS");
+            pregenerated::config_hash(cc, cflags, &generation_cflags(split), &out_path)
+        });
+        pregenerated::load(&dir, &out_path, config_hash.as_deref());
+        return;
     }
 
+    let (cc, cflags_string) =
+        riot_config.expect("Please pass in RIOT_CC; see README.md for details.");
+
     // pass CC and CFLAGS to dependees
     // this requires a `links = "riot-sys"` directive in Cargo.toml.
     // Dependees can then access these as DEP_RIOT_SYS_CC and DEP_RIOT_SYS_CFLAGS.
-    println!("cargo:CC={}", &cc);
-    println!("cargo:CFLAGS={}", &cflags);
+    pregenerated::emit(format!("CC={}", &cc));
+    pregenerated::emit(format!("CFLAGS={}", &cflags_string));
 
     println!("cargo:rerun-if-changed=riot-bindgen.h");
 
-    let cflags = shlex::split(&cflags).expect("Odd shell escaping in RIOT_CFLAGS");
-    let cflags: Vec<String> = cflags
-        .into_iter()
-        .filter(|x| {
-            match x.as_ref() {
-                // These will be in riotbuild.h as well, and better there because bindgen emits
-                // consts for data from files but not from defines (?)
-                x if x.starts_with("-D") => false,
-                // Don't pollute the riot-sys source directory -- cargo is run unconditionally
-                // in the Makefiles, and this script tracks on its own which files to depend on
-                // for rebuilding.
-                "-MD" => false,
-                // accept all others
-                _ => true,
-            }
-        })
-        .collect();
+    let cflags = shlex::split(&cflags_string).expect("Odd shell escaping in RIOT_CFLAGS");
 
-    let bindings = builder()
-        .header("riot-bindgen.h")
-        .clang_args(&cflags)
-        .use_core()
-        .ctypes_prefix("core::ffi")
-        // This is the defensive choice in presence of odd targets, as it forces us to be explicit
-        // more often: Bindgen would err with "Target platform requires `--no-size_t-is-usize`." in
-        // some cases, even though we have not encountered them yet.
-        .size_t_is_usize(false)
-        .impl_debug(true)
-        // Structs listed here are Packed and thus need impl_debug, but also contain non-Copy
-        // members.
-        //
-        // This is a workaround for <https://github.com/rust-lang/rust-bindgen/issues/2221>; once
-        // that is fixed and our bindgen is updated, these can just go away again.
-        //
-        // If you see any errors like
-        //
-        // ```
-        // error: reference to packed field is unaligned
-        //      --> .../out/bindings.rs:79797:13
-        //       |
-        // 79797 |             self.opcode, self.length, self.data
-        //       |             ^^^^^^^^^^^
-        //       |
-        //       = note: `#[deny(unaligned_references)]` on by default
-        //       = warning: this was previously accepted by the compiler but is being phased out; it will become a hard error in a future release!
-        //       = note: for more information, see issue #82523 <https://github.com/rust-lang/rust/issues/82523>
-        //       = note: fields of packed structs are not properly aligned, and creating a misaligned reference is undefined behavior (even if that reference is never dereferenced)
-        //       = help: copy the field contents to a local variable, or replace the reference with a raw pointer and use `read_unaligned`/`write_unaligned` (loads and stores via `*p` must be properly aligned even when using raw pointers)
-        //       = note: this error originates in the macro `$crate::format_args` (in Nightly builds, run with -Z macro-backtrace for more info)
-        // ```
-        //
-        // please add the offending struct in here; if existing code depends on the Debug
-        // implementation, you may add a Debug implementation (that possibly is just a dummy, for
-        // in these cases it *is* hard to implement showing all details) to this crate for the
-        // duration of these workarounds.
-        .no_debug("ble_hci_cmd")
-        .no_debug("ble_hci_ev_command_complete")
-        .no_debug("ble_hci_ev_le_subev_big_complete")
-        .no_debug("ble_hci_ev_le_subev_big_sync_established")
-        .no_debug("ble_hci_ev_le_subev_create_big_complete")
-        .no_debug("ble_hci_ev_le_subev_cs_subevent_result")
-        .no_debug("ble_hci_ev_le_subev_cs_subevent_result_continue")
-        .no_debug("ble_hci_ev_le_subev_periodic_adv_rpt")
-        .no_debug("ble_hci_iso")
-        .no_debug("ble_hci_iso_data")
-        .no_debug("ble_hci_le_big_create_sync_cp")
-        .no_debug("ble_hci_le_cs_test_cp")
-        .no_debug("ble_hci_le_set_cig_params_cp")
-        .no_debug("ble_hci_le_set_cig_params_rp")
-        .no_debug("ble_hci_le_set_cig_params_test_cp")
-        .no_debug("ble_hci_le_set_cig_params_test_rp")
-        .no_debug("ble_hci_le_setup_iso_data_path_cp")
-        .no_debug("ext_adv_report")
-        .derive_default(true)
-        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
-        .generate()
-        .expect("Unable to generate bindings");
+    // Dependees compiling C code of their own need the CFLAGS in parts, as DEP_RIOT_SYS_INCLUDES
+    // etc.; see the cflags_parts module for details
+    for line in cflags_parts::CflagsParts::split(&cflags).cargo_lines() {
+        pregenerated::emit(line);
+    }
 
-    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
-    let bindgen_outfilename = out_path.join("bindings.rs");
-    bindings
-        .write_to_file(&bindgen_outfilename)
-        .expect("Couldn't write bindings!");
-    // Store for inspection for markers; see there
-    let mut bindgen_output = Vec::<u8>::new();
-    bindings
-        .write(Box::new(&mut bindgen_output))
-        .expect("String writing never fails");
-    let bindgen_output = std::str::from_utf8(&bindgen_output).expect("Rust source code is UTF-8");
-
-    // Build a compile_commands.json, and run C2Rust
-    //
-    // The output is cleared beforehand (for c2rust no-ops when an output file is present), and the
-    // input is copied to OUT_DIR as that's the easiest way to get c2rust to put the output file in
-    // a different place -- and because some additions are generated anyway.
+    // The defines are stripped below, but the configuration data in there is exported first; see
+    // the riot_defines module for details. Dependees can access the values as DEP_RIOT_SYS_BOARD
+    // etc.
+    let defines = riot_defines::collect(&cflags);
+    for cfg in riot_defines::CFGS {
+        pregenerated::emit(format!("rustc-check-cfg=cfg({}, values(any()))", cfg));
+    }
+    for (cfg, value) in riot_defines::config(&defines) {
+        pregenerated::emit(format!("rustc-cfg={}={:?}", cfg, value));
+        pregenerated::emit(format!(
+            "{}={}",
+            cfg.trim_start_matches("riot_").to_uppercase(),
+            value
+        ));
+    }
 
-    let c2rust_infile = "riot-c2rust.h";
-    // Follows from c2rust_infile and C2Rust's file name translation scheme
-    let c2rust_output = out_path.join("riot_c2rust.rs");
-    let headercopy = out_path.join(c2rust_infile);
-    println!("cargo:rerun-if-changed=riot-c2rust.h");
+    // Release-based cfgs and constants; see the riot_version module for details. Dependees can
+    // access the version string as DEP_RIOT_SYS_RIOT_VERSION.
+    let riot_version = riot_version::RiotVersion::from_defines(&defines);
+    for line in riot_version::cfgs(riot_version.as_ref()) {
+        pregenerated::emit(line);
+    }
+    if let Some(riot_version) = riot_version.as_ref() {
+        pregenerated::emit(format!("RIOT_VERSION={}", riot_version.string));
+    }
 
-    std::fs::copy("riot-headers.h", out_path.join("riot-headers.h"))
-        .expect("Failed to copy over header file");
+    #[cfg(not(feature = "riot-rs"))]
+    let features = riot_features::Features::from_env();
+    #[cfg(feature = "riot-rs")]
+    let features = riot_features::Features::from_build_info(&build_info);
+    for line in riot_features::cfgs(&features) {
+        pregenerated::emit(line);
+    }
+
+    let cflags = generation_cflags(cflags);
+
+    if cc.find("clang") == None {
+        panic!("riot-sys only accepts clang style CFLAGS. RIOT can produce them using the compile_commands tool even when using a non-clang compiler, such as GCC.");
+    };
+
+    // See the target_check module for details
+    target_check::check(&cflags);
+
+    std::fs::write(
+        out_path.join("riot_version.rs"),
+        riot_version
+            .as_ref()
+            .map(|v| v.constants())
+            .unwrap_or_default(),
+    )
+    .expect("Failed to write riot_version.rs");
+
+    println!("cargo:rerun-if-env-changed=C2RUST");
+    let c2rust = std::env::var("C2RUST").unwrap_or_else(|_| "c2rust".to_string());
+    let c2rust_found = match c2rust_probe::find_binary(&c2rust) {
+        Some(binary) => {
+            println!("cargo:rerun-if-changed={}", binary.display());
+            true
+        }
+        None => {
+            // Can't pinpoint what would be run, so any change might make a difference
+            println!("cargo:rerun-if-env-changed=PATH");
+            false
+        }
+    };
+
+    // Without C2Rust, static inline functions and the macro_ and variadic_ functions are compiled
+    // into a library of shims that are linked instead. That works with any RIOT function, but
+    // nothing of it gets inlined into Rust code, and the functions can not be const.
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_NO_INLINE");
+    let no_inline = env::var("CARGO_FEATURE_NO_INLINE").is_ok() || !c2rust_found;
+    if no_inline {
+        println!(
+            "cargo:warning=Building riot-sys without C2Rust ({}): Static inline functions are \
+             linked as shims and can not be inlined into Rust code.",
+            if c2rust_found {
+                "no-inline feature is enabled".to_string()
+            } else {
+                format!("{} not found; set C2RUST to use a different binary", c2rust)
+            }
+        );
+    }
+
+    // With C2Rust in place, shims can still be built on top, so that static inline functions
+    // whose C2Rust translation is unavailable (or defective) can be called. Which backend is used
+    // for which function is configured in static_inline_policies.
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_STATIC_INLINE_SHIMS");
+    let static_inline_shims = no_inline || env::var("CARGO_FEATURE_STATIC_INLINE_SHIMS").is_ok();
+
+    // See the c_types module for details
+    let c_types = c_types::CTypes::probe(&cc, &cflags, &out_path);
+    if c_types.is_none() {
+        println!(
+            "cargo:warning=Sizes of size_t and related types could not be determined; \
+             riot_sys::c_types stays empty."
+        );
+    }
+    let size_t_is_usize = c_types
+        .as_ref()
+        .map(|c_types| c_types.size_t_is_usize())
+        .unwrap_or(false);
+    std::fs::write(
+        out_path.join("c_types.rs"),
+        c_types
+            .map(|c_types| c_types.generate())
+            .unwrap_or_default(),
+    )
+    .expect("Failed to write c_types.rs");
 
     // These constant initializers are unusable without knowledge of which type they're for; adding
     // the information here to build explicit consts
@@ -330,12 +285,100 @@ fn main() {
         ));
     }
 
-    let mut c_code = String::new();
-    std::fs::File::open("riot-c2rust.h")
-        .expect("Failed to open riot-c2rust.h")
-        .read_to_string(&mut c_code)
-        .expect("Failed to read riot-c2rust.h");
+    // Variadic C functions can be called from Rust, but that leaves all type checking of the
+    // arguments to the caller. These fixed-argument wrappers cover the commonly used cases; like
+    // the macro_ functions, they are transpiled, and available as `variadic_{name}`.
+    let variadic_wrappers = [
+        // printf style output, eg. from shell commands
+        (
+            "printf_str",
+            "int",
+            "const char *format, const char *arg",
+            "printf(format, arg)",
+        ),
+        (
+            "printf_int",
+            "int",
+            "const char *format, int arg",
+            "printf(format, arg)",
+        ),
+        (
+            "printf_uint",
+            "int",
+            "const char *format, unsigned arg",
+            "printf(format, arg)",
+        ),
+        (
+            "printf_ptr",
+            "int",
+            "const char *format, const void *arg",
+            "printf(format, arg)",
+        ),
+        // Logging through the LOG macro, which filters by LOG_LEVEL and goes through whichever
+        // log backend is configured
+        (
+            "log_str",
+            "void",
+            "unsigned level, const char *format, const char *arg",
+            "LOG(level, format, arg)",
+        ),
+        (
+            "log_int",
+            "void",
+            "unsigned level, const char *format, int arg",
+            "LOG(level, format, arg)",
+        ),
+        (
+            "log_uint",
+            "void",
+            "unsigned level, const char *format, unsigned arg",
+            "LOG(level, format, arg)",
+        ),
+    ];
+
+    /// How a static inline function is made available when static_inline_shims is active
+    #[derive(Clone, Copy, PartialEq)]
+    enum StaticInlinePolicy {
+        /// Only transpiled through C2Rust; no shim is built.
+        ///
+        /// This is implemented by blocklisting the function in bindgen, so it may only be used for
+        /// functions that are static inline on all platforms. It does not apply in no-inline mode.
+        Inline,
+        /// A shim is built, and used in the root module even if the function is listed in
+        /// toplevel_from_inline. (The C2Rust version is still available in the inline module).
+        Shim,
+        /// A shim is built, but the C2Rust version takes precedence in the root module if the
+        /// function is listed in toplevel_from_inline. This applies to all functions not listed
+        /// in static_inline_policies.
+        Both,
+    }
+    use StaticInlinePolicy::*;
+
+    let static_inline_policies = [
+        // C2Rust transpiles these into Rust with conflicting lifetimes, and their translation is
+        // disabled; see c2rust_passes::function_signatures
+        ("__SMLALD", Shim),
+        ("__SMLALDX", Shim),
+        ("__SMLSLD", Shim),
+        ("__SMLSLDX", Shim),
+        // Small and frequently used; these transpile well, and a shim would only add to the
+        // library's size
+        ("thread_getpid", Inline),
+        ("mutex_trylock", Inline),
+        ("pid_is_valid", Inline),
+    ];
+    let static_inline_policy = |name: &str| {
+        static_inline_policies
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, policy)| *policy)
+            .unwrap_or(Both)
+    };
 
+    let mut c_wrappers = String::new();
+    // Declarations of the same functions, for bindgen: Were the definitions given to bindgen, they
+    // would end up in the wrappers of wrap_static_fns as well, and be defined twice in the shims.
+    let mut c_prototypes = String::new();
     for (macro_name, return_type, mut args, _is_const, fallback_value) in macro_functions.iter() {
         let expression = match args {
             None => macro_name.to_string(),
@@ -377,7 +420,7 @@ fn main() {
         if *return_type == "void" {
             // in C, assigning and returning void is special
             write!(
-                c_code,
+                c_wrappers,
                 r"
 
 #ifdef {macro_name}
@@ -391,7 +434,7 @@ fn main() {
             )
         } else {
             write!(
-                c_code,
+                c_wrappers,
                 r"
 
 #ifdef {macro_name}
@@ -407,9 +450,19 @@ fn main() {
         }
         .unwrap();
 
+        let prototype = format!(
+            "{return_type} macro_{macro_name}({});\n",
+            args.unwrap_or("void")
+        );
+        if fallback_value.is_some() {
+            c_prototypes.push_str(&prototype);
+        } else {
+            write!(c_prototypes, "#ifdef {macro_name}\n{prototype}#endif\n").unwrap();
+        }
+
         if let Some(fallback_value) = fallback_value {
             writeln!(
-                c_code,
+                c_wrappers,
                 r"
 #else
 {return_type} macro_{macro_name}({args}) {{
@@ -420,231 +473,283 @@ fn main() {
             )
             .unwrap();
         }
-        writeln!(c_code, r" #endif").unwrap();
+        writeln!(c_wrappers, r" #endif").unwrap();
     }
 
-    let mut outfile =
-        std::fs::File::create(&headercopy).expect("Failed to open temporary riot-c2rust.h");
-    outfile
-        .write_all(c_code.as_bytes())
-        .expect("Failed to write to riot-c2rust.h");
-    outfile
-        .sync_all()
-        .expect("failed to write to riot-c2rust.h");
-
-    if cc.find("clang") == None {
-        panic!("riot-sys only accepts clang style CFLAGS. RIOT can produce them using the compile_commands tool even when using a non-clang compiler, such as GCC.");
-    };
+    writeln!(c_wrappers, "\n#include <stdio.h>\n#include <log.h>").unwrap();
+    writeln!(c_prototypes, "\n#include <stdio.h>\n#include <log.h>").unwrap();
+    for (name, return_type, args, expression) in variadic_wrappers.iter() {
+        writeln!(c_prototypes, "{return_type} variadic_{name}({args});").unwrap();
+        let statement = if *return_type == "void" {
+            format!("{expression};")
+        } else {
+            format!("return {expression};")
+        };
+        writeln!(
+            c_wrappers,
+            r"
+{return_type} variadic_{name}({args}) {{
+    {statement}
+}}"
+        )
+        .unwrap();
+    }
 
-    let arguments: Vec<_> = core::iter::once("any-cc".to_string())
-        .chain(cflags.into_iter())
-        .chain(core::iter::once(c2rust_infile.to_string()))
-        .collect();
-    let compile_commands = json!([{
-        "arguments": arguments,
-        "directory": out_path,
-        "file": c2rust_infile,
-    }]);
-    let compile_commands_name = out_path.join("compile_commands.json");
+    // Headers and uses of static inline functions contributed by the application, eg. for its
+    // own C modules; these are processed along with riot-headers.h.
+    println!("cargo:rerun-if-env-changed=RIOT_SYS_EXTRA_HEADERS");
+    println!("cargo:rerun-if-env-changed=RIOT_SYS_EXTRA_USE");
+    let extra_headers = env::var("RIOT_SYS_EXTRA_HEADERS").unwrap_or_default();
+    let extra_headers: Vec<&str> = extra_headers.split_whitespace().collect();
+    let extra_use = env::var("RIOT_SYS_EXTRA_USE").unwrap_or_default();
+    // Dependees can access this as DEP_RIOT_SYS_EXTRA_HEADERS to check for the bindings they need.
+    pregenerated::emit(format!("EXTRA_HEADERS={}", extra_headers.join(" ")));
 
-    let mut compile_commands_file = std::fs::File::create(compile_commands_name.clone())
-        .expect("Failed to create compile_commands.json");
-    serde_json::to_writer_pretty(&mut compile_commands_file, &compile_commands)
-        .expect("Failed to write to compile_commands.json");
-    compile_commands_file
-        .sync_all()
-        .expect("Failed to write to compile_commands.json");
+    let extra_header = out_path.join("riot-sys-extra.h");
+    let mut extra_code = String::new();
+    for header in extra_headers.iter() {
+        writeln!(extra_code, "#include \"{}\"", header).unwrap();
+    }
+    std::fs::write(&extra_header, extra_code).expect("Failed to write riot-sys-extra.h");
 
-    let compile_commands_name = compile_commands_name
-        .to_str()
-        .expect("Inexpressible path name");
-
-    println!("cargo:rerun-if-env-changed=C2RUST");
-    println!("cargo:rerun-if-env-changed=PATH");
-    let c2rust = std::env::var("C2RUST").unwrap_or_else(|_| "c2rust".to_string());
-    let c2rust_version = std::process::Command::new(&c2rust)
-        .args(&["--version"])
-        .output()
-        .expect("C2Rust version check did not complete")
-        .stdout;
-    let c2rust_version = String::from_utf8_lossy(&c2rust_version);
-    print!("C2Rust binary {}, version: {}", c2rust, c2rust_version);
-    // Version won't tell us whether --translate-const-macros needs a TRANSLATE_CONST_MACROS
-    // argument or not, because people can also install from git
-    let c2rust_help = std::process::Command::new(&c2rust)
-        .args(&["transpile", "--help"])
-        .output()
-        .expect("C2Rust --help did not work")
-        .stdout;
-    let c2rust_help =
-        core::str::from_utf8(&c2rust_help).expect("C2Rust help had non-UTF8 characters");
-    let translate_const_macros_arg = if c2rust_help.contains("TRANSLATE_CONST_MACROS") {
-        "--translate-const-macros=conservative"
-    } else {
-        // versions up to 0.20
-        "--translate-const-macros"
-    };
-    // FIXME: This does not rat on the used files. Most are probably included from riot-bindgen.h
-    // anyway, tough.
-    println!("Running C2Rust on {}", compile_commands_name);
-    let status = std::process::Command::new(&c2rust)
-        .args(&[
-            "transpile",
-            compile_commands_name,
-            "--emit-modules",
-            "--emit-no-std",
-            translate_const_macros_arg,
-            "--overwrite-existing",
-            "--fail-on-error",
-        ])
-        .status()
-        .expect("C2Rust failed");
-    if !status.success() {
-        println!(
-            "cargo:warning=C2Rust failed with error code {}, exiting",
-            status
-        );
-        std::process::exit(status.code().unwrap_or(1));
+    let mut bindgen_builder = bindgen_base(&cflags, size_t_is_usize).header("riot-bindgen.h");
+    if !extra_headers.is_empty() {
+        bindgen_builder = bindgen_builder.header(extra_header.display().to_string());
     }
-
-    // Some fix-ups to the C2Rust output
-    // (could just as well call sed...)
-
-    use std::io::{Read, Write};
-
-    let mut rustcode = String::new();
-    std::fs::File::open(c2rust_output)
-        .expect("Failed to open riot_c2rust.rs")
-        .read_to_string(&mut rustcode)
-        .expect("Failed to read from riot_c2rust.rs");
-
-    rustcode = rustcode.replace("use ::libc;\n", "");
-
-    if !c2rust_version.contains("+git-for-riot") && c2rust_version.contains("C2Rust 0.15") {
-        // Old C2Rust still generate old-style ASM -- workaround for https://github.com/immunant/c2rust/issues/306
-        rustcode = rustcode.replace(" asm!(", " llvm_asm!(");
+    // See the bindgen_items module for how applications restrict the output
+    let mut item_filter = bindgen_items::ItemFilter::from_env();
+    if no_inline && item_filter.is_restricted() {
+        // These stand in for the C2Rust translated ones, and thus are always needed
+        item_filter.allow.push("macro_.*".to_string());
+        item_filter.allow.push("variadic_.*".to_string());
+    }
+    bindgen_builder = item_filter.apply(bindgen_builder);
+    if no_inline {
+        bindgen_builder = bindgen_builder
+            // Declares the macro_ and variadic_ functions that are compiled into the shims
+            .header_contents("riot-sys-shims.h", &c_prototypes);
+    }
+    if static_inline_shims {
+        bindgen_builder = bindgen_builder
+            // Produces a C file with exported wrappers for all static inline functions, and binds
+            // to those wrappers instead
+            .wrap_static_fns(true)
+            .wrap_static_fns_path(out_path.join("riot_sys_static_fns"));
+        if !no_inline {
+            for (name, policy) in static_inline_policies.iter() {
+                if *policy == Inline {
+                    bindgen_builder = bindgen_builder.blocklist_function(name);
+                }
+            }
+        }
     }
+    let bindings = bindgen_builder
+        .generate()
+        .expect("Unable to generate bindings");
 
-    // Workaround for https://github.com/immunant/c2rust/issues/372
-    rustcode = rustcode.replace("::core::intrinsics::", "crate::intrinsics_replacements::");
+    // Some fix-ups to the bindgen output; see the bindgen_passes module for details. The result
+    // is kept for inspection for markers; see there
+    let mut bindgen_passes_context = bindgen_passes::Context::default();
+    let bindgen_output =
+        bindgen_passes::process(&bindings.to_string(), &mut bindgen_passes_context)
+            .unwrap_or_else(|e| panic!("Failed to parse bindgen output: {}", e));
+    let bindgen_output = bindgen_output.as_str();
+    let bindgen_outfilename = out_path.join("bindings.rs");
+    std::fs::write(&bindgen_outfilename, bindgen_output).expect("Couldn't write bindings!");
 
-    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_KEEP_EXTERN_TYPES");
-    if env::var("CARGO_FEATURE_KEEP_EXTERN_TYPES").is_err() {
-        // For documentation on why we do this, see include in src/inline.rs.
+    // Rust enum companions for the C enums; see the bindgen_enums module for details
+    let enums = bindgen_enums::generate(bindgen_output)
+        .unwrap_or_else(|e| panic!("Failed to parse bindgen output: {}", e));
+    std::fs::write(out_path.join("enums.rs"), enums).expect("Failed to write enums.rs");
 
-        let pubtypepattern = regex::Regex::new("pub type (?P<type>[a-zA-Z0-9_]+);").unwrap();
-        let pubtypes = pubtypepattern
-            .captures_iter(&rustcode)
-            .map(|m| m.name("type").unwrap().as_str());
+    // Families of flags that get a type in the flags module, by the common prefix of their
+    // constants, the flags among them if the prefix also matches other constants (eg. masks like
+    // O_ACCMODE), the name of the type and where its raw type comes from; see the bindgen_flags
+    // module for details
+    use bindgen_flags::RawType::{Parameter, Typedef};
+    let flag_families = [
+        (
+            "THREAD_CREATE_",
+            None,
+            "ThreadCreate",
+            Parameter("thread_create", "flags"),
+        ),
+        (
+            "SOCK_FLAGS_",
+            None,
+            "SockFlags",
+            Parameter("sock_udp_create", "flags"),
+        ),
+        (
+            "GCOAP_SOCKET_TYPE_",
+            None,
+            "GcoapSocketType",
+            Typedef("gcoap_socket_type_t"),
+        ),
+        (
+            "O_",
+            Some(
+                &[
+                    "RDONLY", "WRONLY", "RDWR", "APPEND", "CREAT", "TRUNC", "EXCL", "NONBLOCK",
+                    "SYNC", "NOCTTY",
+                ][..],
+            ),
+            "OpenFlags",
+            Parameter("vfs_open", "flags"),
+        ),
+        // The category bits of SAUL classes (which are or-ed with the class ID)
+        (
+            "SAUL_CAT_",
+            Some(&["UNDEF", "ACT", "SENSE"][..]),
+            "SaulCategory",
+            Parameter("saul_reg_find_type", "type_"),
+        ),
+    ];
+    let flags = bindgen_flags::generate(bindgen_output, &flag_families)
+        .unwrap_or_else(|e| panic!("Failed to parse bindgen output: {}", e));
+    std::fs::write(out_path.join("flags.rs"), flags).expect("Failed to write flags.rs");
 
-        let pubtype_replacements = out_path.join("pubtype_replacements.rs");
-        let mut pubtype_replacements_file = std::fs::File::create(pubtype_replacements)
-            .expect("Failed to create pubtype_replacements.rs");
+    // Error numbers of the libc; see the errno module for details
+    let errnos = errno::find(&cc, &cflags, &out_path);
+    let tiny_strerror = bindgen_passes_context
+        .declared_fns
+        .contains("tiny_strerror");
+    std::fs::write(
+        out_path.join("errno.rs"),
+        errno::generate(&errnos, tiny_strerror),
+    )
+    .expect("Failed to write errno.rs");
 
-        for pt in pubtypes {
-            writeln!(
-                pubtype_replacements_file,
-                "pub type {} = [u8; isize::MAX as _];",
-                pt
-            )
-            .expect("Failed to write to pubtype_replacements.rs");
+    // Modules from EXTERNAL_MODULE_DIRS; see the external_modules module for details
+    let external_module_dirs = {
+        #[cfg(not(feature = "riot-rs"))]
+        {
+            println!("cargo:rerun-if-env-changed=EXTERNAL_MODULE_DIRS");
+            env::var("EXTERNAL_MODULE_DIRS").unwrap_or_default()
+        }
+        #[cfg(feature = "riot-rs")]
+        {
+            build_info.get("EXTERNAL_MODULE_DIRS")
         }
+    };
+    let external_modules = external_modules::find(&cflags, &external_module_dirs);
+
+    let mut static_fns = vec![out_path.join("riot_sys_static_fns.c")];
+    // Included into the C2Rust input, and static inline functions to be used there
+    let mut external_includes = String::new();
+    let mut external_functions = vec![];
+    // Types from the main bindings are used from there
+    let main_types = if external_modules.is_empty() {
+        Default::default()
+    } else {
+        external_modules::main_types(bindgen_output)
+    };
+    for module in external_modules.iter() {
+        let header_name = format!("external_{}.h", module.name);
+        let header = out_path.join(&header_name);
+        std::fs::write(&header, module.header()).expect("Failed to write external module header");
+        writeln!(external_includes, "#include \"{}\"", header_name).unwrap();
 
-        rustcode = pubtypepattern
-            .replace_all(&rustcode, "/* $0 */")
+        // Static inline functions are always wrapped, as that makes bindgen tell which they are;
+        // the wrappers are only built with static-inline-shims.
+        let wrappers = out_path.join(format!("riot_sys_static_fns_{}", module.name));
+        let module_bindings = bindgen_base(&cflags, size_t_is_usize)
+            .header("riot-bindgen.h")
+            .header(header.display().to_string())
+            .allowlist_file(module.file_pattern())
+            .raw_line("use crate::*;")
+            .wrap_static_fns(true)
+            .wrap_static_fns_path(&wrappers)
+            .generate()
+            .expect("Unable to generate bindings for external module")
             .to_string();
+        if static_inline_shims {
+            static_fns.push(wrappers.with_extension("c"));
+        }
+        let mut passes_context = bindgen_passes::Context {
+            blocked_types: main_types.clone(),
+            strip_wrapped_static_fns: !static_inline_shims,
+            ..Default::default()
+        };
+        let module_bindings = bindgen_passes::process(&module_bindings, &mut passes_context)
+            .unwrap_or_else(|e| panic!("Failed to parse bindgen output: {}", e));
+        std::fs::write(
+            out_path.join(format!("external_{}.rs", module.name)),
+            module_bindings,
+        )
+        .expect("Couldn't write bindings for external module");
+        external_functions.push(passes_context.wrapped_static_fns);
     }
 
-    // On 64-bit native, what gets emitted as vprintf(_, _, _: __builtin_va_list) gets emitted as
-    // vprintf(_, _, _: core::ffi::VaList), which is unsupported in stable -- but we don't use that
-    // function, it's just an unfortunate side effect of --preserve-unused-functions. This quick
-    // workaround enables building and ensures that the function is never called.
-    rustcode = rustcode.replace("::core::ffi::VaList", "::core::convert::Infallible");
-    rustcode = rustcode.replace("__arg.as_va_list()", "__arg");
-
-    // Replace the function declarations with ... usually something pub, but special considerations
-    // may apply
-    let mut rustcode_functionsreplaced = String::new();
-    let function_original_prefix = r#"unsafe extern "C" fn "#;
-    let mut functionchunks = rustcode.split(function_original_prefix);
-    rustcode_functionsreplaced.push_str(
-        functionchunks
-            .next()
-            .expect("Split produces at least a hit"),
-    );
+    if static_inline_shims {
+        build_shims(
+            &cc,
+            &cflags,
+            &out_path,
+            no_inline.then_some(&c_wrappers),
+            &static_fns,
+        );
+    }
+
+    // Appended to the C2Rust input; like in the use_everything of riot-c2rust.h, the uses make
+    // C2Rust emit the static inline functions used in there.
+    let mut c2rust_extra = String::from("\n#include \"riot-sys-extra.h\"\n");
+    c2rust_extra.push_str(&external_includes);
+    let mut extra_use = extra_use;
+    for function in external_functions.iter().flatten() {
+        writeln!(extra_use, "\n(void)&{};", function).unwrap();
+    }
+    if !extra_use.trim().is_empty() {
+        writeln!(
+            c2rust_extra,
+            "\nvoid use_everything_extra(void) {{\n{}\n}}",
+            extra_use
+        )
+        .unwrap();
+    }
 
-    for chunk in functionchunks {
-        let funcname = &chunk[..chunk.find('(').expect("Function has parentheses somewhere")];
-        let macro_details = if funcname.len() > 5 && &funcname[..6] == "macro_" {
+    let inline_items = if no_inline {
+        // Without C2Rust, there are no extern types to replace
+        for file in ["riot_c2rust_replaced.rs", "pubtype_replacements.rs"] {
+            std::fs::write(out_path.join(file), "").expect("Failed to write empty inline module");
+        }
+        vec![]
+    } else {
+        transpile_inline(
+            &c2rust,
+            &cflags,
+            &cc,
+            &out_path,
+            &format!("{}{}", c_wrappers, c2rust_extra),
             macro_functions
                 .iter()
-                .filter(|(macro_name, _, _, _, _)| &funcname[6..] == *macro_name)
-                .next()
-        } else {
-            None
-        };
-        let new_prefix = match (funcname, macro_details) {
-            // used as a callback, therefore does need the extern "C" -- FIXME probably worth a RIOT issue
-            ("_evtimer_msg_handler" | "_evtimer_mbox_handler", _) => function_original_prefix,
-
-            // Assigned by CMSIS to the const that is being overridden and thus needs its original
-            // "C" type; see also riot-c2rust.h. (Actually using it would cause a linker error
-            // anyway).
-            ("__masked_builtin_arm_get_fpscr" | "__masked_builtin_arm_set_fpscr", _) => {
-                function_original_prefix
-            }
-
-            // same problem but from C2Rust's --translate-const-macros
-            ("__NVIC_SetPriority", _) => function_original_prefix,
-
-            // As below (no need for extern), and they are const as declared ni the macro_functions
-            // list.
-            (_, Some((_, _, _, is_const, _))) => {
-                // No "pub" because that's already a "pub" in front of it, they were never static
-                match is_const {
-                    // FIXME: These should be unsafe -- just because most of them are const doesn't
-                    // necessrily mean they're safe (just the first few happened to be, but that's
-                    // not this crate's place to assert)
-                    true => "const unsafe fn ",
-                    false => "unsafe fn ",
-                }
-            }
+                .map(|(macro_name, _, _, is_const, _)| (macro_name.clone(), *is_const))
+                .collect(),
+        )
+    };
 
-            // C2Rust transpiles these into Rust with conflicting lifetimes, see
-            // https://github.com/immunant/c2rust/issues/309
-            //
-            // Simply disabling them here because they aren't used by any other inline code (and
-            // will, when the manual llvm_asm to asm changes are added to riot-sys, not have manual
-            // asm conversions on top of that).
-            ("__SMLALD" | "__SMLALDX" | "__SMLSLD" | "__SMLSLDX", _) => {
-                "#[cfg(c2rust_fixed_309)]\npub unsafe fn "
-            }
+    use std::io::Write;
 
-            // The rest we don't need to call through the extern convention, but let's please make
-            // them pub to be usable
-            _ => "pub unsafe fn ",
-        };
-        rustcode_functionsreplaced.push_str(new_prefix);
-        rustcode_functionsreplaced.push_str(chunk);
+    // Each external module gets the bindgen output for its headers, plus the C2Rust translations
+    // of its static inline functions (unless they are provided as shims)
+    let mut external = String::new();
+    for (module, functions) in external_modules.iter().zip(external_functions.iter()) {
+        let from_inline: Vec<&str> = functions
+            .iter()
+            .filter(|f| !static_inline_shims && inline_items.contains(f))
+            .map(|f| f.as_str())
+            .collect();
+        // Relative to OUT_DIR, so that this stays valid when stored as pregenerated bindings
+        writeln!(
+            external,
+            "pub mod {0} {{\n    include!(concat!(env!(\"OUT_DIR\"), \"/external_{0}.rs\"));\n    \
+             pub use crate::inline::{{ {1} }};\n}}",
+            module.name,
+            from_inline.join(", ")
+        )
+        .unwrap();
     }
-
-    rustcode = rustcode_functionsreplaced;
-
-    // We don't even need that function any more, we just have that in there so that c2rust gives
-    // us its callees as functions. (In bindgen, we'd just list the functions we'd like to have).
-    rustcode = rustcode.replace(
-        "pub pub unsafe fn use_everything()",
-        "#[cfg(false)] unsafe fn use_everything()",
-    );
-    // The macro_ wrappers are already `pub` because they are regular functions we create here that
-    // just go around the macro code; removing the double pub.
-    rustcode = rustcode.replace("pub pub unsafe fn ", "pub unsafe fn ");
-
-    let output_replaced = out_path.join("riot_c2rust_replaced.rs");
-    std::fs::File::create(output_replaced)
-        .expect("Failed to create riot_c2rust_replaced.rs")
-        .write(rustcode.as_bytes())
-        .expect("Failed to write to riot_c2rust_replaced.rs");
+    std::fs::write(out_path.join("external.rs"), external).expect("Failed to write external.rs");
 
     // Pub uses of inline right into the main lib.rs
     //
@@ -695,15 +800,14 @@ fn main() {
     for (macro_name, _, _, _, _) in macro_functions.iter() {
         toplevel_from_inline.push(format!("macro_{}", macro_name));
     }
+    for (name, _, _, _) in variadic_wrappers.iter() {
+        toplevel_from_inline.push(format!("variadic_{}", name));
+    }
     let toplevel_from_inline: Vec<String> = toplevel_from_inline
         .drain(..)
-        .filter(|s: &String| {
-            // Not just matching on `pub fn`: `irq_disable` on native is visible as `extern "C" {
-            // fn irq_disable(); }`, and that should not trigger going through C2Rust.
-            rustcode.contains(&format!("pub fn {}(", s))
-                || rustcode.contains(&format!("unsafe fn {}(", s))
-                || rustcode.contains(&format!(" {}: ", s))
-        })
+        .filter(|s: &String| inline_items.contains(s))
+        // The shim is exported through bindgen instead
+        .filter(|s: &String| !(static_inline_shims && static_inline_policy(s) == Shim))
         .collect();
     let toplevel_from_inline_filename = out_path.join("toplevel_from_inline.rs");
     std::fs::File::create(toplevel_from_inline_filename)
@@ -765,30 +869,508 @@ fn main() {
             Never => false,
         };
         if found {
-            println!("cargo:MARKER_{}=1", name);
+            pregenerated::emit(format!("MARKER_{}=1", name));
         }
     }
 
     // let downstream crates know we're building for riot-rs
     #[cfg(feature = "riot-rs")]
-    println!("cargo:MARKER_riot_rs=1");
+    pregenerated::emit("MARKER_riot_rs=1".to_string());
 
-    println!(
-        "cargo:BINDGEN_OUTPUT_FILE={}",
+    pregenerated::emit(format!(
+        "BINDGEN_OUTPUT_FILE={}",
         bindgen_outfilename.display()
+    ));
+
+    // Report changes in the API compared to a known set of bindings (eg. a snapshot taken with
+    // riot-sys-snapshot, or pregenerated bindings) as warnings; the full report is written next
+    // to the bindings.
+    println!("cargo:rerun-if-env-changed=RIOT_SYS_API_BASELINE");
+    if let Some(baseline) = env::var_os("RIOT_SYS_API_BASELINE") {
+        let baseline = Path::new(&baseline);
+        println!("cargo:rerun-if-changed={}", baseline.display());
+        let old = api_diff::Api::load(baseline).unwrap_or_else(|e| panic!("{}", e));
+        let new = api_diff::Api::load(&out_path).expect("Generated bindings are readable");
+        let report = api_diff::compare(&old, &new);
+        let report_path = out_path.join("api_diff.txt");
+        std::fs::write(&report_path, report.to_string()).expect("Failed to write api_diff.txt");
+        if !report.is_empty() {
+            println!(
+                "cargo:warning=API differs from the baseline in {}: {}",
+                baseline.display(),
+                report.summary()
+            );
+            let report = report.to_string();
+            let lines: Vec<_> = report.lines().collect();
+            for line in lines.iter().take(API_DIFF_WARNING_LINES) {
+                println!("cargo:warning={}", line);
+            }
+            if lines.len() > API_DIFF_WARNING_LINES {
+                println!(
+                    "cargo:warning=... see {} for the full report",
+                    report_path.display()
+                );
+            }
+        }
+    }
+
+    println!("cargo:rerun-if-env-changed=RIOT_SYS_SAVE_PREGENERATED_DIR");
+    if let Some(dir) = env::var_os("RIOT_SYS_SAVE_PREGENERATED_DIR") {
+        let config_hash = pregenerated::config_hash(&cc, &cflags_string, &cflags, &out_path);
+        pregenerated::save(Path::new(&dir), &out_path, &config_hash);
+    }
+}
+
+/// The CFLAGS as they are used for running the C compiler, bindgen and C2Rust here
+fn generation_cflags(cflags: Vec<String>) -> Vec<String> {
+    cflags
+        .into_iter()
+        .filter(|x| {
+            match x.as_ref() {
+                // These will be in riotbuild.h as well, and better there because bindgen emits
+                // consts for data from files but not from defines (?)
+                x if x.starts_with("-D") => false,
+                // Don't pollute the riot-sys source directory -- cargo is run unconditionally
+                // in the Makefiles, and this script tracks on its own which files to depend on
+                // for rebuilding.
+                "-MD" => false,
+                // accept all others
+                _ => true,
+            }
+        })
+        .collect()
+}
+
+/// Read the C compiler and the CFLAGS from the compile commands or the RIOT_CC and RIOT_CFLAGS
+/// variables, or return `None` if neither is configured
+///
+/// With compile commands, the used modules (which are only queried then) are added as defines.
+fn read_cc_cflags(usemodule: impl FnOnce() -> String) -> Option<(String, String)> {
+    let cc;
+    let mut cflags;
+
+    #[cfg(not(feature = "riot-rs"))]
+    let compile_commands_json = "RIOT_COMPILE_COMMANDS_JSON";
+    #[cfg(feature = "riot-rs")]
+    let compile_commands_json = "DEP_RIOT_BUILD_COMPILE_COMMANDS_JSON";
+
+    println!("cargo:rerun-if-env-changed=BUILDING_RIOT_RS");
+    println!("cargo:rerun-if-env-changed=RIOT_CC");
+    println!("cargo:rerun-if-env-changed=RIOT_CFLAGS");
+    println!("cargo:rerun-if-env-changed={}", &compile_commands_json);
+
+    if let Ok(commands_json) = env::var(compile_commands_json) {
+        println!("cargo:rerun-if-changed={}", commands_json);
+        let commands_file = std::fs::File::open(&commands_json)
+            .expect(&format!("Failed to open {}", &commands_json));
+
+        #[derive(Debug, serde::Deserialize)]
+        struct Entry {
+            arguments: Vec<String>,
+        }
+        let parsed: Vec<Entry> = serde_json::from_reader(commands_file)
+            .expect(&format!("Failed to parse {}", &compile_commands_json));
+
+        // We need to find a consensus list -- otherwise single modules like stdio_uart that
+        // defines anything odd for its own purpose can throw things off. (It's not like the actual
+        // ABI compatibility should suffer from them, for any flags like enum packing need to be
+        // the same systemwide anyway for things to to go very wrong) -- but at any rate, finding
+        // some consensus is to some extent necessary here).
+        //
+        // This is relatively brittle, but still better than the previous approach of just taking
+        // the first entry.
+        //
+        // A good long-term solution might be to take CFLAGS as the build system produces them, but
+        // pass them through the LLVMization process of create_compile_commands without actually
+        // turning them into compile commands.
+        let mut consensus_cc: Option<&str> = None;
+        let mut consensus_cflag_groups: Option<Vec<Vec<&str>>> = None;
+        for entry in parsed.iter() {
+            if let Some(consensus_cc) = consensus_cc.as_ref() {
+                assert!(consensus_cc == &entry.arguments[0])
+            } else {
+                consensus_cc = Some(&entry.arguments[0]);
+            }
+            let arg_iter = entry.arguments[1..]
+                .iter()
+                .map(|s| s.as_str())
+                // Anything after -c is not CFLAGS but concrete input/output stuff.
+                .take_while(|&s| s != "-c" && s != "-MQ");
+            // Heuristically grouping them to drop different arguments as whole group
+            let mut cflag_groups = vec![];
+            for mut arg in arg_iter {
+                if arg.starts_with("-I") {
+                    // -I arguments are given inconsistently with and without trailing slashes;
+                    // removing them keeps them from being pruned from the consensus set
+                    arg = arg.trim_end_matches('/');
+                }
+                if arg.starts_with('-') {
+                    cflag_groups.push(vec![arg]);
+                } else {
+                    cflag_groups
+                        .last_mut()
+                        .expect("CFLAG options all start with a dash")
+                        .push(arg);
+                }
+            }
+            if let Some(consensus_cflag_groups) = consensus_cflag_groups.as_mut() {
+                if &cflag_groups != consensus_cflag_groups {
+                    // consensus is in a good ordering, so we'll just strip it down
+                    *consensus_cflag_groups = consensus_cflag_groups
+                        .drain(..)
+                        .filter(|i| {
+                            let mut keep = cflag_groups.contains(i);
+                            // USEMODULE_INCLUDES are sometimes not in all of the entries; see note
+                            // on brittleness above.
+                            keep |= i[0].starts_with("-I");
+                            // Left as multiple lines to ease hooking in with debug statements when
+                            // something goes wrong again...
+                            keep
+                        })
+                        .collect();
+                    // Hot-fixing the merging algorithm to even work when an (always to be kept) -I
+                    // is not in the initial set
+                    for group in cflag_groups.drain(..) {
+                        if group[0].starts_with("-I") {
+                            if !consensus_cflag_groups.contains(&group) {
+                                consensus_cflag_groups.push(group);
+                            }
+                        }
+                    }
+                }
+            } else {
+                consensus_cflag_groups = Some(cflag_groups);
+            }
+        }
+        cc = consensus_cc
+            .expect("Entries are present in compile_commands.json")
+            .to_string();
+        cflags = shlex::try_join(consensus_cflag_groups.unwrap().iter().flatten().map(|s| *s))
+            .expect("Input is not expected to contain NUL characters");
+
+        for m in usemodule().split(" ") {
+            // Hack around https://github.com/RIOT-OS/RIOT/pull/16129#issuecomment-805810090
+            write!(
+                cflags,
+                " -DMODULE_{}",
+                m.to_uppercase()
+                    // avoid producing MODULE_BOARDS_COMMON_SAMDX1-ARDUINO-BOOTLOADER
+                    .replace('-', "_")
+            )
+            .unwrap();
+        }
+    } else {
+        cc = match env::var("RIOT_CC") {
+            Ok(cc) => cc,
+            Err(_) => return None,
+        };
+        cflags = env::var("RIOT_CFLAGS")
+            .expect("Please pass in RIOT_CFLAGS; see README.md for details.");
+    }
+
+    Some((cc, cflags))
+}
+
+/// Create a bindgen builder with the settings shared by all bindgen runs
+fn bindgen_base(cflags: &[String], size_t_is_usize: bool) -> bindgen::Builder {
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_DERIVE_EQ_HASH");
+    let derive_eq_hash = env::var("CARGO_FEATURE_DERIVE_EQ_HASH").is_ok();
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_ZEROCOPY");
+    let zerocopy = env::var("CARGO_FEATURE_ZEROCOPY").is_ok();
+
+    let mut builder = builder()
+        .clang_args(cflags)
+        .use_core()
+        .ctypes_prefix("core::ffi")
+        // Only where the sizes were found to match: Bindgen would err with "Target platform
+        // requires `--no-size_t-is-usize`." otherwise.
+        .size_t_is_usize(size_t_is_usize)
+        .impl_debug(true)
+        // Packed structs with non-Copy members get their Debug implementations replaced; see
+        // bindgen_passes::packed_debug
+        .derive_default(true)
+        // Bindgen only derives these where all fields allow it
+        .derive_partialeq(derive_eq_hash)
+        .derive_eq(derive_eq_hash)
+        .derive_hash(derive_eq_hash)
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()));
+    if zerocopy {
+        builder = builder.parse_callbacks(Box::new(bindgen_callbacks::ZerocopyDerives));
+    }
+    builder
+}
+
+/// Build a compile_commands.json, and run C2Rust
+///
+/// The output is cleared beforehand (for c2rust no-ops when an output file is present), and the
+/// input is copied to OUT_DIR as that's the easiest way to get c2rust to put the output file in
+/// a different place -- and because some additions are generated anyway.
+///
+/// The C2Rust output is placed in riot_c2rust_replaced.rs and pubtype_replacements.rs in OUT_DIR;
+/// the names of the items in there are returned.
+fn transpile_inline(
+    c2rust: &str,
+    cflags: &[String],
+    cc: &str,
+    out_path: &Path,
+    c_wrappers: &str,
+    macro_functions: Vec<(String, bool)>,
+) -> Vec<String> {
+    let c2rust_hash = c2rust_probe::binary_hash(c2rust);
+    let c2rust_capabilities = c2rust_probe::probe_cached(c2rust, c2rust_hash.as_deref(), out_path);
+    println!(
+        "C2Rust binary {}, version: {}, capabilities: {:?}",
+        c2rust, c2rust_capabilities.version, c2rust_capabilities
     );
+
+    let c2rust_infile = "riot-c2rust.h";
+    // Follows from c2rust_infile and C2Rust's file name translation scheme
+    let c2rust_output = out_path
+        .join(&c2rust_capabilities.module_dir)
+        .join("riot_c2rust.rs");
+    let headercopy = out_path.join(c2rust_infile);
+    println!("cargo:rerun-if-changed=riot-c2rust.h");
+    println!("cargo:rerun-if-changed=riot-headers.h");
+
+    std::fs::copy("riot-headers.h", out_path.join("riot-headers.h"))
+        .expect("Failed to copy over header file");
+
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_C_VARIADIC");
+    let c_variadic = env::var("CARGO_FEATURE_C_VARIADIC").is_ok();
+
+    let mut c_code = String::new();
+    if c_variadic {
+        // Allows riot-headers.h to include headers whose static inline functions take a va_list
+        writeln!(c_code, "#define RIOT_SYS_C_VARIADIC").unwrap();
+    }
+    let target_arch = env::var("CARGO_CFG_TARGET_ARCH").expect("Set by Cargo");
+    println!("cargo:rerun-if-changed=src/intrinsics_replacements.rs");
+    let intrinsics_replacements = std::fs::read_to_string("src/intrinsics_replacements.rs")
+        .expect("Failed to read src/intrinsics_replacements.rs");
+    if c2rust_capabilities.atomics_for(&target_arch, &intrinsics_replacements) {
+        // Disables the stdatomic.h workarounds in riot-c2rust.h
+        writeln!(c_code, "#define RIOT_SYS_C2RUST_ATOMICS").unwrap();
+    }
+    std::fs::File::open("riot-c2rust.h")
+        .expect("Failed to open riot-c2rust.h")
+        .read_to_string(&mut c_code)
+        .expect("Failed to read riot-c2rust.h");
+    c_code.push_str(c_wrappers);
+
+    let mut outfile =
+        std::fs::File::create(&headercopy).expect("Failed to open temporary riot-c2rust.h");
+    outfile
+        .write_all(c_code.as_bytes())
+        .expect("Failed to write to riot-c2rust.h");
+    outfile
+        .sync_all()
+        .expect("failed to write to riot-c2rust.h");
+
+    let arguments: Vec<_> = core::iter::once("any-cc".to_string())
+        .chain(cflags.iter().cloned())
+        .chain(core::iter::once(c2rust_infile.to_string()))
+        .collect();
+    let compile_commands = json!([{
+        "arguments": arguments,
+        "directory": out_path,
+        "file": c2rust_infile,
+    }]);
+    let compile_commands_name = out_path.join("compile_commands.json");
+
+    let mut compile_commands_file = std::fs::File::create(compile_commands_name.clone())
+        .expect("Failed to create compile_commands.json");
+    serde_json::to_writer_pretty(&mut compile_commands_file, &compile_commands)
+        .expect("Failed to write to compile_commands.json");
+    compile_commands_file
+        .sync_all()
+        .expect("Failed to write to compile_commands.json");
+
+    let compile_commands_name = compile_commands_name
+        .to_str()
+        .expect("Inexpressible path name");
+
+    // The preprocessed input stands in for all the files it includes when looking up cached
+    // results; see the c2rust_cache module for details.
+    //
+    // On the side, this produces the list of all files C2Rust will read.
+    let depfile = out_path.join("riot-c2rust.d");
+    let preprocessed = c2rust_cache::preprocess(cc, cflags, out_path, c2rust_infile, &depfile);
+    let depfile = std::fs::read_to_string(&depfile).expect("Failed to read riot-c2rust.d");
+    for dependency in parse_depfile(&depfile) {
+        let dependency = out_path.join(dependency);
+        // Files in OUT_DIR are generated right here (from files that are tracked already);
+        // tracking them would cause rebuilds every time.
+        if !dependency.starts_with(out_path) {
+            println!("cargo:rerun-if-changed={}", dependency.display());
+        }
+    }
+
+    println!("cargo:rerun-if-env-changed=CARGO_FEATURE_KEEP_EXTERN_TYPES");
+    let keep_extern_types = env::var("CARGO_FEATURE_KEEP_EXTERN_TYPES").is_ok();
+
+    // Caching is only possible when the C2Rust binary can be identified
+    let cache = c2rust_hash.as_ref().and_then(|c2rust_hash| {
+        let cache_dir = c2rust_cache::cache_dir(out_path)?;
+        let key = c2rust_cache::key(
+            &preprocessed,
+            cflags,
+            c2rust_hash,
+            &format!(
+                "keep_extern_types={} c_variadic={}",
+                keep_extern_types, c_variadic
+            ),
+        );
+        Some((cache_dir, key))
+    });
+    let cached_items = cache.as_ref().and_then(|(cache_dir, key)| {
+        let items = c2rust_cache::load(cache_dir, key, out_path)?;
+        println!(
+            "Using cached C2Rust output from {}",
+            cache_dir.join(key).display()
+        );
+        Some(items)
+    });
+
+    use std::io::{Read, Write};
+
+    if let Some(items) = cached_items {
+        items
+    } else {
+        println!("Running C2Rust on {}", compile_commands_name);
+        let status = std::process::Command::new(&c2rust)
+            .args(&[
+                "transpile",
+                compile_commands_name,
+                "--emit-modules",
+                "--emit-no-std",
+                &c2rust_capabilities.translate_const_macros_arg,
+                "--overwrite-existing",
+                "--fail-on-error",
+            ])
+            .status()
+            .expect("C2Rust failed");
+        if !status.success() {
+            println!(
+                "cargo:warning=C2Rust failed with error code {}, exiting",
+                status
+            );
+            std::process::exit(status.code().unwrap_or(1));
+        }
+
+        // Some fix-ups to the C2Rust output; see the c2rust_passes module for details
+
+        let mut rustcode = String::new();
+        std::fs::File::open(c2rust_output)
+            .expect("Failed to open riot_c2rust.rs")
+            .read_to_string(&mut rustcode)
+            .expect("Failed to read from riot_c2rust.rs");
+
+        let mut passes_context = c2rust_passes::Context {
+            legacy_asm: c2rust_capabilities.legacy_asm,
+            keep_extern_types,
+            keep_va_list: c_variadic,
+            macro_functions,
+            ..Default::default()
+        };
+        let rustcode = c2rust_passes::process(&rustcode, &mut passes_context)
+            .unwrap_or_else(|e| panic!("Failed to parse C2Rust output riot_c2rust.rs: {}", e));
+
+        if !keep_extern_types {
+            // For documentation on why we do this, see include in src/inline.rs.
+            let pubtype_replacements = out_path.join("pubtype_replacements.rs");
+            let mut pubtype_replacements_file = std::fs::File::create(pubtype_replacements)
+                .expect("Failed to create pubtype_replacements.rs");
+
+            for pt in passes_context.extern_types.iter() {
+                writeln!(
+                    pubtype_replacements_file,
+                    "pub type {} = [u8; isize::MAX as _];",
+                    pt
+                )
+                .expect("Failed to write to pubtype_replacements.rs");
+            }
+        }
+
+        let output_replaced = out_path.join("riot_c2rust_replaced.rs");
+        std::fs::File::create(output_replaced)
+            .expect("Failed to create riot_c2rust_replaced.rs")
+            .write(rustcode.as_bytes())
+            .expect("Failed to write to riot_c2rust_replaced.rs");
+
+        if let Some((cache_dir, key)) = cache.as_ref() {
+            c2rust_cache::store(cache_dir, key, out_path, &passes_context.items);
+        }
+
+        passes_context.items
+    }
 }
 
-#[cfg(feature = "riot-rs")]
-fn get_riot_var(riot_builddir: &str, var: &str) -> String {
-    let output = std::process::Command::new("sh")
-        .arg("-c")
-        .arg(format!(
-            "{} make --no-print-directory -C {} TOOLCHAIN=llvm info-debug-variable-{}",
-            "WARNING_EXTERNAL_MODULE_DIRS=0", riot_builddir, var
-        ))
-        .output()
-        .unwrap()
-        .stdout;
-    String::from_utf8_lossy(output.as_slice()).trim_end().into()
+/// Compile the shims into a static library
+///
+/// The shims are the wrappers bindgen generated for static inline functions, and (in no-inline
+/// mode, where they are not transpiled) the macro_ and variadic_ functions passed in as
+/// `c_wrappers`.
+fn build_shims(
+    cc: &str,
+    cflags: &[String],
+    out_path: &Path,
+    c_wrappers: Option<&String>,
+    static_fns: &[PathBuf],
+) {
+    let mut build = cc::Build::new();
+    build
+        .compiler(cc)
+        // The CFLAGS are complete, and the target is expressed in there already
+        .no_default_flags(true)
+        .include(env::var("CARGO_MANIFEST_DIR").expect("Set by cargo"));
+    if let Some(c_wrappers) = c_wrappers {
+        let shims = out_path.join("riot_sys_shims.c");
+        std::fs::write(
+            &shims,
+            format!("#include \"riot-headers.h\"\n{}", c_wrappers),
+        )
+        .expect("Failed to write riot_sys_shims.c");
+        build.file(&shims);
+    }
+    for flag in cflags {
+        build.flag(flag);
+    }
+    for static_fns in static_fns {
+        // Only created if there are any static functions
+        if static_fns.exists() {
+            build.file(static_fns);
+        }
+    }
+    build.compile("riot_sys_shims");
+}
+
+/// Extract the dependencies from a Makefile style dependency file as produced by `-MD`
+///
+/// This assumes a single target (whose name is ignored), as is produced for a single input file.
+fn parse_depfile(depfile: &str) -> Vec<String> {
+    let depfile = depfile.replace("\\\r\n", " ").replace("\\\n", " ");
+    let dependencies = match depfile.split_once(": ") {
+        Some((_target, dependencies)) => dependencies,
+        None => return vec![],
+    };
+
+    let mut result = vec![];
+    let mut current = String::new();
+    let mut chars = dependencies.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            // Escaped space (or other character) in file names
+            '\\' => current.extend(chars.next()),
+            c if c.is_whitespace() => {
+                if !current.is_empty() {
+                    result.push(core::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        result.push(current);
+    }
+    result
 }
@@ -0,0 +1,467 @@
+//! Comparison of the API of two sets of generated bindings
+//!
+//! A set of bindings is a directory as produced in OUT_DIR (or stored as pregenerated bindings,
+//! or as a snapshot): Its `bindings.rs` is bindgen's output, and its `riot_c2rust_replaced.rs`
+//! (if present) contains the C2Rust translations of the static inline functions. A single file is
+//! taken to be a `bindings.rs`.
+//!
+//! The report lists items that were added or removed, items whose signature changed (with struct
+//! and union fields compared one by one), and functions that moved between being linked and being
+//! static inline.
+//!
+//! This is used by the riot-sys-api-diff tool, and in the build when `RIOT_SYS_API_BASELINE`
+//! points to a set of bindings to compare to.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use syn::{parse_quote, Item};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Kind {
+    /// A function that is linked
+    Function,
+    /// A static inline function translated by C2Rust
+    InlineFunction,
+    Static,
+    Const,
+    Struct,
+    Union,
+    Enum,
+    TypeAlias,
+}
+
+impl Kind {
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Function => "fn",
+            Kind::InlineFunction => "static inline fn",
+            Kind::Static => "static",
+            Kind::Const => "const",
+            Kind::Struct => "struct",
+            Kind::Union => "union",
+            Kind::Enum => "enum",
+            Kind::TypeAlias => "type",
+        }
+    }
+
+    fn is_function(self) -> bool {
+        matches!(self, Kind::Function | Kind::InlineFunction)
+    }
+}
+
+struct ApiItem {
+    kind: Kind,
+    /// The item without attributes and bodies, on a single line
+    signature: String,
+    /// Names and types of the fields of structs and unions
+    fields: Vec<(String, String)>,
+}
+
+/// The items of a set of bindings
+#[derive(Default)]
+pub struct Api {
+    /// Items by name; types and values are kept apart as their names can coincide
+    items: BTreeMap<(bool, String), ApiItem>,
+}
+
+/// Format an item on a single line
+fn oneline(item: Item) -> String {
+    let text = prettyplease::unparse(&syn::File {
+        shebang: None,
+        attrs: vec![],
+        items: vec![item],
+    });
+    // Long items are broken into lines with trailing commas
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("( ", "(")
+        .replace(", )", ")")
+        .replace(", }", " }")
+}
+
+fn type_string(ty: &syn::Type) -> String {
+    let text = oneline(parse_quote!(type T = #ty;));
+    text.trim_start_matches("type T = ")
+        .trim_end_matches(';')
+        .to_string()
+}
+
+fn function_signature(vis: &syn::Visibility, sig: &syn::Signature) -> String {
+    let text = oneline(Item::Fn(syn::ItemFn {
+        attrs: vec![],
+        vis: vis.clone(),
+        sig: sig.clone(),
+        block: Box::new(parse_quote!({})),
+    }));
+    text.trim_end_matches(" {}").to_string()
+}
+
+fn fields(fields: &syn::Fields) -> Vec<(String, String)> {
+    fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| {
+            let name = field
+                .ident
+                .as_ref()
+                .map(|i| i.to_string())
+                .unwrap_or_else(|| i.to_string());
+            (name, type_string(&field.ty))
+        })
+        .collect()
+}
+
+impl Api {
+    /// Load a set of bindings from a directory (or a single bindings file)
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut api = Self::default();
+        let (bindings, inline) = if path.is_dir() {
+            (
+                path.join("bindings.rs"),
+                Some(path.join("riot_c2rust_replaced.rs")),
+            )
+        } else {
+            (path.to_path_buf(), None)
+        };
+
+        let read = |path: &Path| -> Result<syn::File, String> {
+            let code = std::fs::read_to_string(path)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            syn::parse_file(&code).map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+        };
+
+        api.add_bindgen(read(&bindings)?);
+        if let Some(inline) = inline.filter(|i| i.exists()) {
+            api.add_inline(read(&inline)?);
+        }
+        Ok(api)
+    }
+
+    fn insert(
+        &mut self,
+        name: String,
+        kind: Kind,
+        signature: String,
+        fields: Vec<(String, String)>,
+    ) {
+        let is_type = matches!(
+            kind,
+            Kind::Struct | Kind::Union | Kind::Enum | Kind::TypeAlias
+        );
+        self.items.insert(
+            (is_type, name),
+            ApiItem {
+                kind,
+                signature,
+                fields,
+            },
+        );
+    }
+
+    fn add_bindgen(&mut self, file: syn::File) {
+        for item in file.items {
+            match item {
+                Item::ForeignMod(foreign) => {
+                    for item in foreign.items {
+                        match item {
+                            syn::ForeignItem::Fn(f) => self.insert(
+                                f.sig.ident.to_string(),
+                                Kind::Function,
+                                function_signature(&f.vis, &f.sig),
+                                vec![],
+                            ),
+                            syn::ForeignItem::Static(s) => {
+                                let mutability = match s.mutability {
+                                    syn::StaticMutability::Mut(_) => "mut ",
+                                    _ => "",
+                                };
+                                let signature = format!(
+                                    "pub static {}{}: {}",
+                                    mutability,
+                                    s.ident,
+                                    type_string(&s.ty)
+                                );
+                                self.insert(s.ident.to_string(), Kind::Static, signature, vec![]);
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                Item::Struct(mut s) => {
+                    s.attrs.clear();
+                    s.fields.iter_mut().for_each(|f| f.attrs.clear());
+                    let fields = fields(&s.fields);
+                    self.insert(
+                        s.ident.to_string(),
+                        Kind::Struct,
+                        oneline(Item::Struct(s)),
+                        fields,
+                    );
+                }
+                Item::Union(mut u) => {
+                    u.attrs.clear();
+                    u.fields.named.iter_mut().for_each(|f| f.attrs.clear());
+                    let fields = fields(&syn::Fields::Named(u.fields.clone()));
+                    self.insert(
+                        u.ident.to_string(),
+                        Kind::Union,
+                        oneline(Item::Union(u)),
+                        fields,
+                    );
+                }
+                Item::Enum(mut e) => {
+                    e.attrs.clear();
+                    self.insert(
+                        e.ident.to_string(),
+                        Kind::Enum,
+                        oneline(Item::Enum(e)),
+                        vec![],
+                    );
+                }
+                Item::Type(mut t) => {
+                    t.attrs.clear();
+                    self.insert(
+                        t.ident.to_string(),
+                        Kind::TypeAlias,
+                        oneline(Item::Type(t)),
+                        vec![],
+                    );
+                }
+                Item::Const(mut c) => {
+                    c.attrs.clear();
+                    self.insert(
+                        c.ident.to_string(),
+                        Kind::Const,
+                        oneline(Item::Const(c)),
+                        vec![],
+                    );
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn add_inline(&mut self, file: syn::File) {
+        for item in file.items {
+            if let Item::Fn(f) = item {
+                if matches!(f.vis, syn::Visibility::Public(_)) {
+                    // Functions that are also linked (as shims) are listed as inline
+                    self.insert(
+                        f.sig.ident.to_string(),
+                        Kind::InlineFunction,
+                        function_signature(&f.vis, &f.sig),
+                        vec![],
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Differences between two sets of bindings, each entry as a line of text
+#[derive(Default)]
+pub struct Report {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+    /// Functions that were linked and are now static inline
+    pub to_inline: Vec<String>,
+    /// Functions that were static inline and are now linked
+    pub to_linked: Vec<String>,
+}
+
+/// Compare an old and a new set of bindings
+pub fn compare(old: &Api, new: &Api) -> Report {
+    let mut report = Report::default();
+    for (key, old_item) in old.items.iter() {
+        let name = &key.1;
+        let new_item = match new.items.get(key) {
+            Some(new_item) => new_item,
+            None => {
+                report.removed.push(old_item.signature.clone());
+                continue;
+            }
+        };
+
+        if old_item.kind.is_function()
+            && new_item.kind.is_function()
+            && old_item.kind != new_item.kind
+        {
+            let moved = format!("{}: {}", name, new_item.signature);
+            match new_item.kind {
+                Kind::InlineFunction => report.to_inline.push(moved),
+                _ => report.to_linked.push(moved),
+            }
+            continue;
+        }
+
+        if old_item.kind != new_item.kind {
+            report.changed.push(format!(
+                "{} {} is now a {}: {}",
+                old_item.kind.label(),
+                name,
+                new_item.kind.label(),
+                new_item.signature
+            ));
+        } else if matches!(old_item.kind, Kind::Struct | Kind::Union) {
+            let label = old_item.kind.label();
+            for (field, ty) in old_item.fields.iter() {
+                match new_item.fields.iter().find(|(f, _)| f == field) {
+                    None => report.changed.push(format!(
+                        "{} {}: field {}: {} removed",
+                        label, name, field, ty
+                    )),
+                    Some((_, new_ty)) if new_ty != ty => report.changed.push(format!(
+                        "{} {}: field {} changed from {} to {}",
+                        label, name, field, ty, new_ty
+                    )),
+                    Some(_) => (),
+                }
+            }
+            for (field, ty) in new_item.fields.iter() {
+                if !old_item.fields.iter().any(|(f, _)| f == field) {
+                    report
+                        .changed
+                        .push(format!("{} {}: field {}: {} added", label, name, field, ty));
+                }
+            }
+        } else if old_item.signature != new_item.signature {
+            report.changed.push(format!(
+                "{} {} changed from `{}` to `{}`",
+                old_item.kind.label(),
+                name,
+                old_item.signature,
+                new_item.signature
+            ));
+        }
+    }
+    for (key, new_item) in new.items.iter() {
+        if !old.items.contains_key(key) {
+            report.added.push(new_item.signature.clone());
+        }
+    }
+    report
+}
+
+impl Report {
+    fn sections(&self) -> [(&'static str, &Vec<String>); 5] {
+        [
+            ("Added", &self.added),
+            ("Removed", &self.removed),
+            ("Changed", &self.changed),
+            ("Moved from linked to static inline", &self.to_inline),
+            ("Moved from static inline to linked", &self.to_linked),
+        ]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sections()
+            .iter()
+            .all(|(_, entries)| entries.is_empty())
+    }
+
+    /// A single line stating the number of entries per category
+    pub fn summary(&self) -> String {
+        self.sections()
+            .iter()
+            .filter(|(_, entries)| !entries.is_empty())
+            .map(|(title, entries)| format!("{}: {}", title, entries.len()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (title, entries) in self.sections() {
+            if entries.is_empty() {
+                continue;
+            }
+            writeln!(f, "{} ({}):", title, entries.len())?;
+            for entry in entries {
+                writeln!(f, "    {}", entry)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api(bindings: &str, inline: &str) -> Api {
+        let mut api = Api::default();
+        api.add_bindgen(syn::parse_file(bindings).unwrap());
+        api.add_inline(syn::parse_file(inline).unwrap());
+        api
+    }
+
+    #[test]
+    fn compare_bindings() {
+        let old = api(
+            "extern \"C\" {
+                pub fn gone();
+                pub fn kept(x: u8) -> i32;
+                pub fn retyped(x: u8);
+                pub fn becomes_inline();
+            }
+            #[repr(C)]
+            pub struct s { pub a: u8, pub b: u16 }
+            pub type t = u8;
+            pub const C: u8 = 1;",
+            "pub unsafe extern \"C\" fn becomes_linked() {}",
+        );
+        let new = api(
+            "extern \"C\" {
+                pub fn kept(x: u8) -> i32;
+                pub fn retyped(x: u16);
+                pub fn becomes_linked();
+                pub fn new_fn();
+            }
+            #[repr(C)]
+            #[derive(Debug)]
+            pub struct s { pub a: u32, pub c: u8 }
+            pub struct t { pub a: u8 }
+            pub const C: u8 = 1;",
+            "pub unsafe extern \"C\" fn becomes_inline() {}
+            fn private() {}",
+        );
+        let report = compare(&old, &new);
+        for (section, expected) in [
+            (&report.added, &["pub fn new_fn()"][..]),
+            (&report.removed, &["pub fn gone()"]),
+            (
+                &report.changed,
+                &[
+                    "fn retyped changed from `pub fn retyped(x: u8)` to `pub fn retyped(x: u16)`",
+                    "struct s: field a changed from u8 to u32",
+                    "struct s: field b: u16 removed",
+                    "struct s: field c: u8 added",
+                    "type t is now a struct: pub struct t { pub a: u8 }",
+                ],
+            ),
+            (
+                &report.to_inline,
+                &["becomes_inline: pub unsafe extern \"C\" fn becomes_inline()"],
+            ),
+            (
+                &report.to_linked,
+                &["becomes_linked: pub fn becomes_linked()"],
+            ),
+        ] {
+            assert_eq!(section, expected);
+        }
+        assert_eq!(
+            report.summary(),
+            "Added: 1, Removed: 1, Changed: 5, Moved from linked to static inline: 1, \
+             Moved from static inline to linked: 1"
+        );
+
+        let unchanged = compare(&old, &old);
+        assert!(unchanged.is_empty());
+        assert_eq!(unchanged.summary(), "");
+        assert_eq!(unchanged.to_string(), "");
+    }
+}
@@ -0,0 +1,52 @@
+//! Additional derives on bindgen types through bindgen's `ParseCallbacks`
+
+use bindgen::callbacks::{DeriveInfo, ParseCallbacks, TypeKind};
+
+/// Plain-old-data types that get zerocopy's traits derived, with the option of also deriving
+/// `IntoBytes`
+///
+/// Entries ending in `*` match any type name starting with the rest. Types used in the fields of
+/// the listed types (including anonymous ones, eg. the `_sock_tl_ep__bindgen_ty_1` union of
+/// addresses) need to be listed as well.
+///
+/// `IntoBytes` can only be derived for structs without any padding; deriving it for unions is not
+/// supported by zerocopy without extra configuration, so only structs that don't contain any unions
+/// get it.
+const ZEROCOPY_TYPES: &[(&str, bool)] = &[
+    ("be_uint*", false),
+    ("network_uint*", false),
+    ("ipv6_addr_t", false),
+    // sock_udp_ep_t and the other endpoints
+    ("_sock_tl_ep*", false),
+    ("phydat_t", true),
+    ("uuid_t", false),
+];
+
+/// Derives zerocopy's traits for the [ZEROCOPY_TYPES]
+#[derive(Debug)]
+pub struct ZerocopyDerives;
+
+impl ParseCallbacks for ZerocopyDerives {
+    fn add_derives(&self, info: &DeriveInfo<'_>) -> Vec<String> {
+        let into_bytes = ZEROCOPY_TYPES.iter().find_map(|(pattern, into_bytes)| {
+            let matches = match pattern.strip_suffix('*') {
+                Some(prefix) => info.name.starts_with(prefix),
+                None => info.name == *pattern,
+            };
+            matches.then_some(*into_bytes)
+        });
+        let into_bytes = match into_bytes {
+            Some(into_bytes) => into_bytes,
+            None => return vec![],
+        };
+
+        let mut derives: Vec<String> = ["FromBytes", "Immutable", "KnownLayout"]
+            .iter()
+            .map(|d| format!("zerocopy::{}", d))
+            .collect();
+        if into_bytes && info.kind == TypeKind::Struct {
+            derives.push("zerocopy::IntoBytes".to_string());
+        }
+        derives
+    }
+}
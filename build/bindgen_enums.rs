@@ -0,0 +1,137 @@
+//! Rust enums for RIOT's C enums
+//!
+//! Bindgen translates C enums into a type alias for the underlying integer type and one constant
+//! per enumerator, named `<type>_<enumerator>`. Those stay as they are (they are what the C ABI
+//! uses), but each such enum also gets a Rust enum companion (placed in `riot_sys::enums`) with
+//! one variant per enumerator, conversions from and to the raw type, and a Display of the C name.
+//!
+//! Enums without a name (whose constants bindgen gives `_bindgen_ty_` types) have no type that
+//! they could be converted from, and are left alone.
+
+use super::rust_idents::ident;
+use syn::ext::IdentExt;
+use syn::{parse_quote, Item};
+
+/// A C enum as found in the bindgen output
+struct CEnum {
+    /// Name of the type alias
+    name: String,
+    /// Enumerators by their C name, along with the name of the constant bindgen produced
+    enumerators: Vec<(String, String)>,
+}
+
+/// Find the enums in the (parsed) bindgen output
+fn find(file: &syn::File) -> Vec<CEnum> {
+    let aliases: Vec<String> = file
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Type(alias) => Some(alias.ident.unraw().to_string()),
+            _ => None,
+        })
+        .collect();
+
+    let mut enums: Vec<CEnum> = vec![];
+    for item in file.items.iter() {
+        let konst = match item {
+            Item::Const(konst) => konst,
+            _ => continue,
+        };
+        let ty = match &*konst.ty {
+            syn::Type::Path(path) if path.path.segments.len() == 1 => {
+                path.path.segments[0].ident.unraw().to_string()
+            }
+            _ => continue,
+        };
+        if ty.starts_with("_bindgen_ty_") || !aliases.contains(&ty) {
+            continue;
+        }
+        let const_name = konst.ident.unraw().to_string();
+        let enumerator = match const_name
+            .strip_prefix(ty.as_str())
+            .and_then(|rest| rest.strip_prefix('_'))
+        {
+            Some(enumerator) if !enumerator.is_empty() => enumerator.to_string(),
+            _ => continue,
+        };
+
+        match enums.iter_mut().find(|e| e.name == ty) {
+            Some(e) => e.enumerators.push((enumerator, const_name)),
+            None => enums.push(CEnum {
+                name: ty,
+                enumerators: vec![(enumerator, const_name)],
+            }),
+        }
+    }
+    enums
+}
+
+/// Produce the content of the `enums` module from the bindgen output
+pub fn generate(bindgen_output: &str) -> syn::Result<String> {
+    let file = syn::parse_file(bindgen_output)?;
+
+    let mut items: Vec<Item> = vec![];
+    for e in find(&file) {
+        let name = ident(&e.name);
+        let doc = format!(" Rust enum for the C enum [`{0}`](crate::{0})", e.name);
+        let doc_conversion = " Conversion from the raw value fails for values that are not known at \
+            build time; aliases (enumerators with the same value) are converted to the first of them.";
+        let variants: Vec<syn::Ident> = e.enumerators.iter().map(|(v, _)| ident(v)).collect();
+        let consts: Vec<syn::Ident> = e.enumerators.iter().map(|(_, c)| ident(c)).collect();
+        // Keywords are raw identifiers, but shown by their C name
+        let labels: Vec<String> = variants.iter().map(|v| v.unraw().to_string()).collect();
+
+        let variant_docs = consts
+            .iter()
+            .map(|c| format!(" See [`{0}`](crate::{0})", c));
+
+        let file: syn::File = parse_quote! {
+            #[doc = #doc]
+            #[doc = ""]
+            #[doc = #doc_conversion]
+            #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+            #[non_exhaustive]
+            pub enum #name {
+                #(
+                    #[doc = #variant_docs]
+                    #variants,
+                )*
+            }
+
+            impl TryFrom<crate::#name> for #name {
+                type Error = crate::#name;
+
+                #[allow(unreachable_patterns, clippy::match_overlapping_arm)]
+                fn try_from(raw: crate::#name) -> Result<Self, crate::#name> {
+                    match raw {
+                        #( crate::#consts => Ok(Self::#variants), )*
+                        _ => Err(raw),
+                    }
+                }
+            }
+
+            impl From<#name> for crate::#name {
+                fn from(value: #name) -> Self {
+                    match value {
+                        #( #name::#variants => crate::#consts, )*
+                    }
+                }
+            }
+
+            impl core::fmt::Display for #name {
+                fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    f.write_str(match self {
+                        #( Self::#variants => #labels, )*
+                    })
+                }
+            }
+        };
+        items.extend(file.items);
+    }
+
+    Ok(prettyplease::unparse(&syn::File {
+        shebang: None,
+        attrs: vec![],
+        items,
+    }))
+}
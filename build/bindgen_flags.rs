@@ -0,0 +1,294 @@
+//! Typed flag sets for families of RIOT flag constants
+//!
+//! Many RIOT APIs take flags that are or-ed together from defines or enumerators sharing a common
+//! prefix (eg. `THREAD_CREATE_SLEEPING | THREAD_CREATE_STACKTEST`). For each family configured in
+//! build.rs, a newtype around the raw integer is placed in `riot_sys::flags`, with one associated
+//! constant per flag (named like the C constant without the prefix), the usual bit operations, and
+//! a Debug implementation that lists the set flags.
+//!
+//! The raw type is the one the flags are passed to RIOT as: that of a function parameter that
+//! takes them, or the typedef of the enum they are enumerators of (see [RawType]). Families for
+//! which that is not in the bindgen output, or none of whose constants are (eg. because the module
+//! is not in use), produce no type.
+
+use super::rust_idents::ident;
+use syn::ext::IdentExt;
+use syn::{parse_quote, Item};
+
+/// Where the raw type of a family of flags is taken from
+pub enum RawType<'a> {
+    /// The type of a function's parameter, by function and parameter name
+    Parameter(&'a str, &'a str),
+    /// A type alias, typically the typedef of the enum the flags are enumerators of
+    Typedef(&'a str),
+}
+
+impl RawType<'_> {
+    /// Find the type in the (parsed) bindgen output
+    fn find(&self, file: &syn::File) -> Option<syn::Type> {
+        match self {
+            RawType::Parameter(function, parameter) => file
+                .items
+                .iter()
+                .filter_map(|item| match item {
+                    Item::ForeignMod(foreign) => Some(foreign.items.iter()),
+                    _ => None,
+                })
+                .flatten()
+                .find_map(|item| match item {
+                    syn::ForeignItem::Fn(f) if f.sig.ident == function => Some(&f.sig.inputs),
+                    _ => None,
+                })?
+                .iter()
+                .find_map(|input| match input {
+                    syn::FnArg::Typed(typed) => match &*typed.pat {
+                        syn::Pat::Ident(name) if name.ident.unraw() == parameter => {
+                            Some((*typed.ty).clone())
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                }),
+            RawType::Typedef(name) => file.items.iter().find_map(|item| match item {
+                Item::Type(alias) if alias.ident == name => {
+                    let ident = &alias.ident;
+                    Some(parse_quote!(#ident))
+                }
+                _ => None,
+            }),
+        }
+    }
+}
+
+/// A family of flags as found in the bindgen output
+struct Family<'a> {
+    /// Name of the Rust type
+    name: &'a str,
+    /// The type the flags are passed to RIOT as, as used in bindgen's output
+    raw: syn::Type,
+    /// Flag names (without the prefix), along with the name of the constant bindgen produced
+    flags: Vec<(String, String)>,
+}
+
+/// Find the constants of a family with the given prefix, limited to the given flag names if any
+///
+/// Defines show up in the bindgen output under their own name; enumerators have their enum's name
+/// prepended.
+fn find<'a>(
+    file: &syn::File,
+    prefix: &str,
+    only: Option<&[&str]>,
+    name: &'a str,
+    raw: syn::Type,
+) -> Option<Family<'a>> {
+    let mut family: Option<Family> = None;
+    for item in file.items.iter() {
+        let konst = match item {
+            Item::Const(konst) => konst,
+            _ => continue,
+        };
+        let const_name = konst.ident.to_string();
+        let enum_name = match &*konst.ty {
+            syn::Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+            _ => None,
+        };
+        let c_name = match enum_name.and_then(|e| {
+            const_name
+                .strip_prefix(&format!("{}_", e))
+                .map(|n| n.to_string())
+        }) {
+            Some(c_name) if c_name.starts_with(prefix) => c_name,
+            _ => const_name.clone(),
+        };
+        let flag = match c_name.strip_prefix(prefix) {
+            Some(flag) if !flag.is_empty() => flag.to_string(),
+            _ => continue,
+        };
+        if matches!(only, Some(only) if !only.contains(&flag.as_str())) {
+            continue;
+        }
+
+        let family = family.get_or_insert_with(|| Family {
+            name,
+            raw: raw.clone(),
+            flags: vec![],
+        });
+        // Defines that are repeated in an enum (or the other way round) are listed once
+        if !family.flags.iter().any(|(f, _)| *f == flag) {
+            family.flags.push((flag, const_name));
+        }
+    }
+    family
+}
+
+/// Make a type from bindgen's output usable from the `flags` module
+fn qualify(ty: syn::Type) -> syn::Type {
+    match ty {
+        syn::Type::Path(path) if path.path.segments.len() == 1 => {
+            let name = path.path.segments[0].ident.to_string();
+            match name.as_str() {
+                "u8" | "u16" | "u32" | "u64" | "i8" | "i16" | "i32" | "i64" => {
+                    syn::Type::Path(path)
+                }
+                _ => parse_quote!(crate::#path),
+            }
+        }
+        ty => ty,
+    }
+}
+
+/// Produce the content of the `flags` module from the bindgen output
+///
+/// Families are given as the common prefix of their constants, the flag names (without the prefix)
+/// if not all constants with that prefix are flags (eg. when there are masks among them), the name
+/// of the Rust type, and where its raw type comes from.
+pub fn generate(
+    bindgen_output: &str,
+    families: &[(&str, Option<&[&str]>, &str, RawType)],
+) -> syn::Result<String> {
+    let file = syn::parse_file(bindgen_output)?;
+
+    let mut items: Vec<Item> = vec![];
+    for (prefix, only, name, raw) in families {
+        let raw = match raw.find(&file) {
+            Some(raw) => raw,
+            None => continue,
+        };
+        let family = match find(&file, prefix, *only, name, raw) {
+            Some(family) => family,
+            None => continue,
+        };
+        let name = ident(family.name);
+        let raw = qualify(family.raw);
+        let doc = format!(" Set of the `{}*` flags", prefix);
+        let flags: Vec<syn::Ident> = family
+            .flags
+            .iter()
+            .map(
+                |(flag, c_name)| match flag.starts_with(|c: char| c.is_ascii_digit()) {
+                    true => ident(c_name),
+                    false => ident(flag),
+                },
+            )
+            .collect();
+        let consts: Vec<syn::Ident> = family.flags.iter().map(|(_, c)| ident(c)).collect();
+        let labels: Vec<String> = flags.iter().map(|f| f.unraw().to_string()).collect();
+        let flag_docs = consts
+            .iter()
+            .map(|c| format!(" See [`{0}`](crate::{0})", c));
+
+        let file: syn::File = parse_quote! {
+            #[doc = #doc]
+            #[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+            #[repr(transparent)]
+            pub struct #name(pub #raw);
+
+            #[allow(clippy::unnecessary_cast)]
+            impl #name {
+                #(
+                    #[doc = #flag_docs]
+                    pub const #flags: Self = Self(crate::#consts as #raw);
+                )*
+
+                const ALL: &'static [(&'static str, Self)] = &[#( (#labels, Self::#flags), )*];
+
+                /// The set without any flags
+                pub const fn empty() -> Self {
+                    Self(0)
+                }
+
+                /// The raw value, as passed to C functions
+                pub const fn bits(self) -> #raw {
+                    self.0
+                }
+
+                /// Build the set from a raw value; bits that are not known flags are kept
+                pub const fn from_bits(bits: #raw) -> Self {
+                    Self(bits)
+                }
+
+                /// Whether all flags that are set in `other` are set in `self`
+                pub const fn contains(self, other: Self) -> bool {
+                    self.0 & other.0 == other.0
+                }
+
+                /// Whether no flags are set
+                pub const fn is_empty(self) -> bool {
+                    self.0 == 0
+                }
+            }
+
+            impl core::ops::BitOr for #name {
+                type Output = Self;
+
+                fn bitor(self, other: Self) -> Self {
+                    Self(self.0 | other.0)
+                }
+            }
+
+            impl core::ops::BitOrAssign for #name {
+                fn bitor_assign(&mut self, other: Self) {
+                    self.0 |= other.0;
+                }
+            }
+
+            impl core::ops::BitAnd for #name {
+                type Output = Self;
+
+                fn bitand(self, other: Self) -> Self {
+                    Self(self.0 & other.0)
+                }
+            }
+
+            impl core::ops::BitAndAssign for #name {
+                fn bitand_assign(&mut self, other: Self) {
+                    self.0 &= other.0;
+                }
+            }
+
+            impl From<#raw> for #name {
+                fn from(bits: #raw) -> Self {
+                    Self(bits)
+                }
+            }
+
+            impl From<#name> for #raw {
+                fn from(flags: #name) -> Self {
+                    flags.0
+                }
+            }
+
+            impl core::fmt::Debug for #name {
+                fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                    write!(f, "{}(", stringify!(#name))?;
+                    let mut rest = self.0;
+                    let mut first = true;
+                    for (label, flag) in Self::ALL {
+                        if flag.0 != 0 && self.contains(*flag) {
+                            if !first {
+                                f.write_str(" | ")?;
+                            }
+                            f.write_str(label)?;
+                            rest &= !flag.0;
+                            first = false;
+                        }
+                    }
+                    if rest != 0 || first {
+                        if !first {
+                            f.write_str(" | ")?;
+                        }
+                        write!(f, "{:#x}", rest)?;
+                    }
+                    f.write_str(")")
+                }
+            }
+        };
+        items.extend(file.items);
+    }
+
+    Ok(prettyplease::unparse(&syn::File {
+        shebang: None,
+        attrs: vec![],
+        items,
+    }))
+}
@@ -0,0 +1,105 @@
+//! Restriction of the items bindgen produces
+//!
+//! By default, bindgen produces everything reachable from riot-bindgen.h, which easily amounts to
+//! 100k lines. Applications that only use a few APIs can cut that down:
+//!
+//! * `RIOT_SYS_BINDGEN_ALLOWLIST` and `RIOT_SYS_BINDGEN_BLOCKLIST` contain whitespace separated
+//!   patterns (regular expressions in bindgen's syntax, eg. `ztimer_.*`) of items to include or
+//!   exclude.
+//! * `RIOT_SYS_BINDGEN_CONFIG` names a file with one `allow <pattern>` or `block <pattern>` per
+//!   line; empty lines and lines starting with `#` are ignored.
+//! * `RIOT_SYS_BINDGEN_USED_ITEMS` names a file that lists the names of the items that are used,
+//!   one per line, as produced by the consuming crate. They are added to the allowlist verbatim.
+//!
+//! Without any allowed item, everything is produced as before. Items that allowed items depend on
+//! (eg. the types of a struct's fields) are always produced.
+
+use std::path::Path;
+
+/// Patterns from all configured sources
+#[derive(Debug, Default)]
+pub struct ItemFilter {
+    pub allow: Vec<String>,
+    pub block: Vec<String>,
+}
+
+impl ItemFilter {
+    /// Gather the patterns from the environment variables and the files they point to
+    pub fn from_env() -> Self {
+        let mut filter = Self::default();
+
+        for (var, list) in [
+            ("RIOT_SYS_BINDGEN_ALLOWLIST", &mut filter.allow),
+            ("RIOT_SYS_BINDGEN_BLOCKLIST", &mut filter.block),
+        ] {
+            println!("cargo:rerun-if-env-changed={}", var);
+            if let Ok(patterns) = std::env::var(var) {
+                list.extend(patterns.split_whitespace().map(|p| p.to_string()));
+            }
+        }
+
+        println!("cargo:rerun-if-env-changed=RIOT_SYS_BINDGEN_CONFIG");
+        if let Ok(config) = std::env::var("RIOT_SYS_BINDGEN_CONFIG") {
+            filter.read_config(Path::new(&config));
+        }
+
+        println!("cargo:rerun-if-env-changed=RIOT_SYS_BINDGEN_USED_ITEMS");
+        if let Ok(used) = std::env::var("RIOT_SYS_BINDGEN_USED_ITEMS") {
+            filter.read_used_items(Path::new(&used));
+        }
+
+        filter
+    }
+
+    fn read_config(&mut self, config: &Path) {
+        println!("cargo:rerun-if-changed={}", config.display());
+        let config = std::fs::read_to_string(config)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", config.display(), e));
+        for line in config.lines().map(|l| l.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(char::is_whitespace) {
+                Some(("allow", pattern)) => self.allow.push(pattern.trim().to_string()),
+                Some(("block", pattern)) => self.block.push(pattern.trim().to_string()),
+                _ => panic!(
+                    "Invalid line in RIOT_SYS_BINDGEN_CONFIG (expected `allow <pattern>` or \
+                     `block <pattern>`): {}",
+                    line
+                ),
+            }
+        }
+    }
+
+    fn read_used_items(&mut self, used: &Path) {
+        println!("cargo:rerun-if-changed={}", used.display());
+        let used = std::fs::read_to_string(used)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", used.display(), e));
+        for name in used.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            // Only identifiers match themselves when used as a pattern
+            if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                panic!(
+                    "Invalid item name in RIOT_SYS_BINDGEN_USED_ITEMS: {:?}",
+                    name
+                );
+            }
+            self.allow.push(name.to_string());
+        }
+    }
+
+    /// Whether any restriction is configured
+    pub fn is_restricted(&self) -> bool {
+        !self.allow.is_empty()
+    }
+
+    /// Add the patterns to a bindgen builder
+    pub fn apply(&self, mut builder: bindgen::Builder) -> bindgen::Builder {
+        for pattern in self.allow.iter() {
+            builder = builder.allowlist_item(pattern);
+        }
+        for pattern in self.block.iter() {
+            builder = builder.blocklist_item(pattern);
+        }
+        builder
+    }
+}
@@ -0,0 +1,373 @@
+//! Fix-ups to the bindgen output
+//!
+//! Like the C2Rust output (see the c2rust_passes module), the bindgen output is parsed into a
+//! syntax tree and run through the list of [PASSES]. Bindgen's own hooks (its `ParseCallbacks`)
+//! can add derives and attributes to items, but not take them away, which is what some of these
+//! need to do.
+//!
+//! Unlike the C2Rust output, the result is not printed from the syntax tree as a whole: Only the
+//! items the passes changed are replaced in the bindgen output, so that everything else stays
+//! exactly as bindgen produced it (which matters to crates that inspect it through
+//! `DEP_RIOT_SYS_BINDGEN_OUTPUT_FILE`).
+
+use quote::ToTokens;
+use std::collections::{HashMap, HashSet};
+use syn::spanned::Spanned;
+use syn::{parse_quote, Item};
+
+/// Configuration for and results of the passes
+#[derive(Default)]
+pub struct Context {
+    /// Types that [blocked_types] removes, along with their implementations
+    pub blocked_types: HashSet<String>,
+    /// Packed structs whose Debug implementation was replaced by [packed_debug]
+    pub packed_debug: Vec<String>,
+    /// Whether [wrapped_static_fns] removes the declarations it finds
+    pub strip_wrapped_static_fns: bool,
+    /// Static functions that bindgen declared through their wrappers, as found by
+    /// [wrapped_static_fns]
+    pub wrapped_static_fns: Vec<String>,
+    /// Functions that the bindings declare, as found by [declared_fns]
+    pub declared_fns: HashSet<String>,
+}
+
+/// A single transformation of the bindgen output
+///
+/// Passes may change items and add new ones at the end, but must not reorder them; items are
+/// removed by replacing them with [removed].
+pub type Pass = fn(&mut syn::File, &mut Context);
+
+/// The passes applied by [process], in the order in which they run
+pub const PASSES: &[Pass] = &[
+    blocked_types,
+    packed_debug,
+    wrapped_static_fns,
+    declared_fns,
+];
+
+/// Parse bindgen output, run all [PASSES] on it, and produce the resulting code
+pub fn process(code: &str, context: &mut Context) -> syn::Result<String> {
+    let mut file = syn::parse_file(code)?;
+    let original: Vec<(std::ops::Range<usize>, String)> = file
+        .items
+        .iter()
+        .map(|item| (item.span().byte_range(), item.to_token_stream().to_string()))
+        .collect();
+
+    for pass in PASSES {
+        pass(&mut file, context);
+    }
+
+    let mut output = String::new();
+    let mut position = 0;
+    for (item, (range, tokens)) in file.items.iter().zip(original.iter()) {
+        if item.to_token_stream().to_string() != *tokens {
+            output.push_str(&code[position..range.start]);
+            output.push_str(unparse(item).trim_end());
+            position = range.end;
+        }
+    }
+    output.push_str(&code[position..]);
+    for item in file.items[original.len()..].iter() {
+        output.push('\n');
+        output.push_str(&unparse(item));
+    }
+    Ok(output)
+}
+
+/// Placeholder for an item a pass removed
+pub fn removed() -> Item {
+    Item::Verbatim(Default::default())
+}
+
+fn unparse(item: &Item) -> String {
+    match item {
+        Item::Verbatim(tokens) if tokens.is_empty() => String::new(),
+        item => prettyplease::unparse(&syn::File {
+            shebang: None,
+            attrs: vec![],
+            items: vec![item.clone()],
+        }),
+    }
+}
+
+/// Give packed structs with non-Copy fields a Debug implementation that does not take references
+/// to their fields
+///
+/// Bindgen would derive Debug (or implement it with references to all fields) for them, which is
+/// rejected as "reference to packed field is unaligned"; this is
+/// <https://github.com/rust-lang/rust-bindgen/issues/2221>. Such structs are recognized by not
+/// deriving Copy (for bindgen derives it wherever it can). The replacement only shows the fields
+/// that can be copied out and shown, and is marked as non-exhaustive.
+pub fn packed_debug(file: &mut syn::File, context: &mut Context) {
+    let copy_types = types_with_trait(file, "Copy");
+    let debug_types = types_with_trait(file, "Debug");
+    let aliases = type_aliases(file);
+
+    let mut replacements = vec![];
+    for item in file.items.iter_mut() {
+        let strukt = match item {
+            Item::Struct(strukt) => strukt,
+            _ => continue,
+        };
+        if !is_packed(&strukt.attrs) || derives(&strukt.attrs, "Copy") {
+            continue;
+        }
+        remove_derive(&mut strukt.attrs, "Debug");
+
+        let name = &strukt.ident;
+        let label = name.to_string();
+        let mut chain: syn::Expr = parse_quote!(f.debug_struct(#label));
+        for field in strukt.fields.iter() {
+            let ident = match field.ident.as_ref() {
+                Some(ident) => ident,
+                None => continue,
+            };
+            // Padding, bitfield units and the like
+            if ident.to_string().starts_with('_') {
+                continue;
+            }
+            if is_copy_debug(&field.ty, &copy_types, &debug_types, &aliases) {
+                let field_label = ident.to_string();
+                chain = parse_quote!(#chain.field(
+                    #field_label,
+                    &unsafe { ::core::ptr::addr_of!(self.#ident).read_unaligned() }
+                ));
+            }
+        }
+        replacements.push(parse_quote! {
+            impl ::core::fmt::Debug for #name {
+                fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                    #chain.finish_non_exhaustive()
+                }
+            }
+        });
+        context.packed_debug.push(label);
+    }
+
+    // Any existing implementation (from impl_debug) is replaced
+    for item in file.items.iter_mut() {
+        if let Item::Impl(imp) = item {
+            if implements(imp, "Debug")
+                && self_ident(imp)
+                    .map(|i| context.packed_debug.contains(&i))
+                    .unwrap_or(false)
+            {
+                *item = removed();
+            }
+        }
+    }
+    file.items.extend(replacements);
+}
+
+/// Remove the types listed in the context's blocked_types, and their implementations
+///
+/// This is used where the types are already defined elsewhere (eg. external modules' bindings
+/// use those of the main bindings). Unlike bindgen's `blocklist_type`, this works on a plain list
+/// of names.
+pub fn blocked_types(file: &mut syn::File, context: &mut Context) {
+    if context.blocked_types.is_empty() {
+        return;
+    }
+    for item in file.items.iter_mut() {
+        let ident = match item {
+            Item::Struct(i) => Some(i.ident.to_string()),
+            Item::Union(i) => Some(i.ident.to_string()),
+            Item::Enum(i) => Some(i.ident.to_string()),
+            Item::Type(i) => Some(i.ident.to_string()),
+            Item::Impl(imp) => self_ident(imp),
+            _ => None,
+        };
+        if matches!(ident, Some(i) if context.blocked_types.contains(&i)) {
+            *item = removed();
+        }
+    }
+}
+
+/// Suffix of the wrappers bindgen produces with `wrap_static_fns` (its default)
+const WRAPPER_SUFFIX: &str = "__extern";
+
+/// Find the static (inline) functions that bindgen declared with `wrap_static_fns`
+///
+/// Bindgen declares them with the link name of their wrapper. Where the wrappers are not built
+/// (because the functions are translated by C2Rust instead), the declarations are removed.
+pub fn wrapped_static_fns(file: &mut syn::File, context: &mut Context) {
+    for item in file.items.iter_mut() {
+        let foreign = match item {
+            Item::ForeignMod(foreign) => foreign,
+            _ => continue,
+        };
+        foreign.items.retain(|item| {
+            let function = match item {
+                syn::ForeignItem::Fn(function) => function,
+                _ => return true,
+            };
+            let wrapped = function.attrs.iter().any(|attr| match &attr.meta {
+                syn::Meta::NameValue(nv) if nv.path.is_ident("link_name") => matches!(
+                    &nv.value,
+                    syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(name), .. })
+                        if name.value().ends_with(WRAPPER_SUFFIX)
+                ),
+                _ => false,
+            });
+            if wrapped {
+                context
+                    .wrapped_static_fns
+                    .push(function.sig.ident.to_string());
+            }
+            !(wrapped && context.strip_wrapped_static_fns)
+        });
+    }
+    for item in file.items.iter_mut() {
+        if matches!(item, Item::ForeignMod(foreign) if foreign.items.is_empty()) {
+            *item = removed();
+        }
+    }
+}
+
+/// Record the names of all the functions the bindings declare
+///
+/// This runs after [wrapped_static_fns], so declarations removed there are not included.
+pub fn declared_fns(file: &mut syn::File, context: &mut Context) {
+    for item in file.items.iter() {
+        if let Item::ForeignMod(foreign) = item {
+            for item in foreign.items.iter() {
+                if let syn::ForeignItem::Fn(function) = item {
+                    context.declared_fns.insert(function.sig.ident.to_string());
+                }
+            }
+        }
+    }
+}
+
+fn is_packed(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path().is_ident("repr")
+            && attr
+                .parse_args_with(
+                    syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated,
+                )
+                .map(|metas| metas.iter().any(|meta| meta.path().is_ident("packed")))
+                .unwrap_or(false)
+    })
+}
+
+fn derived_paths(attr: &syn::Attribute) -> Vec<syn::Path> {
+    if !attr.path().is_ident("derive") {
+        return vec![];
+    }
+    attr.parse_args_with(syn::punctuated::Punctuated::<syn::Path, syn::Token![,]>::parse_terminated)
+        .map(|paths| paths.into_iter().collect())
+        .unwrap_or_default()
+}
+
+fn last_ident_is(path: &syn::Path, name: &str) -> bool {
+    path.segments
+        .last()
+        .map(|s| s.ident == name)
+        .unwrap_or(false)
+}
+
+fn derives(attrs: &[syn::Attribute], name: &str) -> bool {
+    attrs
+        .iter()
+        .flat_map(derived_paths)
+        .any(|p| last_ident_is(&p, name))
+}
+
+fn remove_derive(attrs: &mut [syn::Attribute], name: &str) {
+    for attr in attrs.iter_mut() {
+        let paths = derived_paths(attr);
+        if paths.iter().any(|p| last_ident_is(p, name)) {
+            let kept = paths.iter().filter(|p| !last_ident_is(p, name));
+            *attr = parse_quote!(#[derive(#(#kept),*)]);
+        }
+    }
+}
+
+fn implements(imp: &syn::ItemImpl, name: &str) -> bool {
+    imp.trait_
+        .as_ref()
+        .map(|(_, path, _)| last_ident_is(path, name))
+        .unwrap_or(false)
+}
+
+fn self_ident(imp: &syn::ItemImpl) -> Option<String> {
+    match &*imp.self_ty {
+        syn::Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+        _ => None,
+    }
+}
+
+/// Names of types that derive or implement the trait
+fn types_with_trait(file: &syn::File, name: &str) -> HashSet<String> {
+    let mut types = HashSet::new();
+    for item in file.items.iter() {
+        let (ident, attrs) = match item {
+            Item::Struct(i) => (&i.ident, &i.attrs),
+            Item::Union(i) => (&i.ident, &i.attrs),
+            Item::Enum(i) => (&i.ident, &i.attrs),
+            Item::Impl(imp) if implements(imp, name) => {
+                types.extend(self_ident(imp));
+                continue;
+            }
+            _ => continue,
+        };
+        if derives(attrs, name) {
+            types.insert(ident.to_string());
+        }
+    }
+    types
+}
+
+fn type_aliases(file: &syn::File) -> HashMap<String, syn::Type> {
+    file.items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Type(alias) => Some((alias.ident.to_string(), (*alias.ty).clone())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether a field of the type can be copied out (and its copy shown) in a Debug implementation
+fn is_copy_debug(
+    ty: &syn::Type,
+    copy_types: &HashSet<String>,
+    debug_types: &HashSet<String>,
+    aliases: &HashMap<String, syn::Type>,
+) -> bool {
+    match ty {
+        syn::Type::Ptr(_) => true,
+        syn::Type::Array(array) => is_copy_debug(&array.elem, copy_types, debug_types, aliases),
+        syn::Type::Path(path) => {
+            let last = match path.path.segments.last() {
+                Some(last) => last,
+                None => return false,
+            };
+            let name = last.ident.to_string();
+            match name.as_str() {
+                "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "i8" | "i16" | "i32" | "i64"
+                | "i128" | "isize" | "f32" | "f64" | "bool" | "char" => true,
+                // core::ffi::c_int and friends
+                n if n.starts_with("c_") && path.path.segments.len() > 1 => true,
+                // Function pointers are Option<unsafe extern "C" fn(...)>
+                "Option" => match &last.arguments {
+                    syn::PathArguments::AngleBracketed(args) => match args.args.first() {
+                        Some(syn::GenericArgument::Type(syn::Type::BareFn(_))) => true,
+                        Some(syn::GenericArgument::Type(inner)) => {
+                            is_copy_debug(inner, copy_types, debug_types, aliases)
+                        }
+                        _ => false,
+                    },
+                    _ => false,
+                },
+                n => match aliases.get(n) {
+                    Some(aliased) => is_copy_debug(aliased, copy_types, debug_types, aliases),
+                    None => copy_types.contains(n) && debug_types.contains(n),
+                },
+            }
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,251 @@
+//! Cache for post-processed C2Rust output
+//!
+//! Running C2Rust takes a large share of the build time, but its output only depends on what goes
+//! into it. Entries are keyed by a hash over the preprocessed input, the clang arguments, the
+//! C2Rust binary and riot-sys' own processing code, so that they can be shared between
+//! applications and boards that have identical configurations.
+//!
+//! For that, the key leaves out everything that differs between applications without affecting
+//! the output: The input is preprocessed without line markers (which contain the paths of the
+//! headers), the application specific [APPLICATION_MACROS] are removed, and include paths and
+//! defines are not taken from the arguments (their effect is part of the preprocessed input).
+//!
+//! The cache lives in `RIOT_SYS_CACHE_DIR` if that is set (an empty value disables caching), and
+//! in a `riot-sys-cache` directory in Cargo's target directory otherwise.
+
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Files that are stored in a cache entry, as they are named in OUT_DIR
+pub const FILES: &[&str] = &["riot_c2rust_replaced.rs", "pubtype_replacements.rs"];
+/// File in the cache entry that lists the items found by `c2rust_passes::collect_items`
+const ITEMS_FILE: &str = "inline_items.txt";
+
+/// Macros whose values differ between applications even if they are configured identically
+///
+/// riot-c2rust.h undefines them, so that they do not show up in the cached output either.
+const APPLICATION_MACROS: &[&str] = &["RIOT_APPLICATION"];
+
+/// Options that only affect which files are read, or which macros are defined, along with the
+/// options among them that take their argument as a separate item
+const PREPROCESSOR_OPTIONS: &[&str] = &[
+    "-I",
+    "-isystem",
+    "-iquote",
+    "-idirafter",
+    "-include",
+    "-imacros",
+    "-D",
+    "-U",
+];
+
+/// Preprocess the C2Rust input (`infile` in `dir`) for use in a [key], and write a depfile on the
+/// side
+///
+/// The defines are kept in the output, as they matter for `--translate-const-macros`.
+pub fn preprocess(
+    cc: &str,
+    cflags: &[String],
+    dir: &Path,
+    infile: &str,
+    depfile: &Path,
+) -> Vec<u8> {
+    let preprocessed = std::process::Command::new(cc)
+        .args(cflags)
+        .args(["-E", "-P", "-dD", "-MD", "-MF"])
+        .arg(depfile)
+        .arg(infile)
+        .current_dir(dir)
+        .output()
+        .unwrap_or_else(|e| panic!("Failed to run the C preprocessor on {}: {}", infile, e));
+    if !preprocessed.status.success() {
+        panic!(
+            "Preprocessing {} failed:\n{}",
+            infile,
+            String::from_utf8_lossy(&preprocessed.stderr)
+        );
+    }
+    preprocessed.stdout
+}
+
+/// Find the cache directory, or None if caching is disabled
+pub fn cache_dir(out_dir: &Path) -> Option<PathBuf> {
+    println!("cargo:rerun-if-env-changed=RIOT_SYS_CACHE_DIR");
+    if let Some(configured) = std::env::var_os("RIOT_SYS_CACHE_DIR") {
+        if configured.is_empty() {
+            return None;
+        }
+        return Some(PathBuf::from(configured));
+    }
+
+    let target_dir = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .or_else(|| {
+            // Cargo marks its target directory with a CACHEDIR.TAG
+            out_dir
+                .ancestors()
+                .find(|dir| dir.join("CACHEDIR.TAG").is_file())
+                .map(|dir| dir.to_path_buf())
+        })?;
+    Some(target_dir.join("riot-sys-cache"))
+}
+
+/// Build the key of a cache entry
+///
+/// `settings` should contain everything else that influences the output (eg. enabled features).
+pub fn key(
+    preprocessed: &[u8],
+    clang_args: &[String],
+    c2rust_hash: &str,
+    settings: &str,
+) -> String {
+    let mut hasher = Sha256::new();
+    for line in preprocessed.split(|c| *c == b'\n') {
+        let is_application_macro = APPLICATION_MACROS.iter().any(|name| {
+            line.strip_prefix(b"#define ")
+                .and_then(|rest| rest.strip_prefix(name.as_bytes()))
+                .map(|rest| rest.is_empty() || rest[0] == b' ' || rest[0] == b'(')
+                .unwrap_or(false)
+        });
+        if !is_application_macro {
+            hasher.update(line);
+            hasher.update([b'\n']);
+        }
+    }
+    let mut args = clang_args.iter();
+    while let Some(arg) = args.next() {
+        match PREPROCESSOR_OPTIONS.iter().find(|o| arg.starts_with(**o)) {
+            Some(option) if arg == option => {
+                args.next();
+            }
+            Some(_) => (),
+            None => {
+                hasher.update(arg.as_bytes());
+                hasher.update([0]);
+            }
+        }
+    }
+    hasher.update(c2rust_hash.as_bytes());
+    hasher.update([0]);
+    hasher.update(settings.as_bytes());
+    hasher.update([0]);
+    // Any change to the processing invalidates the cache, even without a version bump
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(include_str!("../build.rs").as_bytes());
+    hasher.update(include_str!("c2rust_passes.rs").as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Copy a cached entry's files into `out_dir`, returning the list of items
+pub fn load(cache_dir: &Path, key: &str, out_dir: &Path) -> Option<Vec<String>> {
+    let entry = cache_dir.join(key);
+    let items = std::fs::read_to_string(entry.join(ITEMS_FILE)).ok()?;
+    for file in FILES {
+        let cached = entry.join(file);
+        if cached.exists() {
+            std::fs::copy(&cached, out_dir.join(file)).ok()?;
+        }
+    }
+    Some(items.lines().map(|l| l.to_string()).collect())
+}
+
+/// Store the files from `out_dir` along with the list of items in a new cache entry
+///
+/// Failure to store is not fatal (the cache may be on a read-only file system); it is reported as
+/// a warning.
+pub fn store(cache_dir: &Path, key: &str, out_dir: &Path, items: &[String]) {
+    if let Err(e) = try_store(cache_dir, key, out_dir, items) {
+        println!(
+            "cargo:warning=Failed to store C2Rust output in cache {}: {}",
+            cache_dir.display(),
+            e
+        );
+    }
+}
+
+fn try_store(cache_dir: &Path, key: &str, out_dir: &Path, items: &[String]) -> std::io::Result<()> {
+    // Populate under a temporary name, so that concurrent builds never see partial entries
+    let staging = cache_dir.join(format!("{}.tmp-{}", key, std::process::id()));
+    std::fs::create_dir_all(&staging)?;
+    for file in FILES {
+        let produced = out_dir.join(file);
+        if produced.exists() {
+            std::fs::copy(&produced, staging.join(file))?;
+        }
+    }
+    std::fs::write(staging.join(ITEMS_FILE), items.join("\n"))?;
+
+    if std::fs::rename(&staging, cache_dir.join(key)).is_err() {
+        // Most likely a concurrent build stored the same entry first
+        std::fs::remove_dir_all(&staging)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Set up an application with a riotbuild.h like RIOT creates it, and find the key for
+    /// preprocessing a C2Rust input in its build directory
+    fn application_key(root: &Path, application: &str, board: &str) -> String {
+        let include = root.join("include");
+        std::fs::create_dir_all(&include).unwrap();
+        std::fs::write(
+            include.join("board.h"),
+            "#define LED0_PIN 13\nstatic inline int board_name_len(void) { return sizeof(RIOT_BOARD); }\n",
+        )
+        .unwrap();
+
+        let riotbuild = root
+            .join(application)
+            .join("bin")
+            .join(board)
+            .join("riotbuild");
+        std::fs::create_dir_all(&riotbuild).unwrap();
+        std::fs::write(
+            riotbuild.join("riotbuild.h"),
+            format!(
+                "#define RIOT_APPLICATION \"{}\"\n#define RIOT_BOARD \"{}\"\n#define MODULE_ZTIMER 1\n",
+                application, board
+            ),
+        )
+        .unwrap();
+
+        let out_dir = root.join(application).join("bin").join(board).join("out");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        std::fs::write(
+            out_dir.join("riot-c2rust.h"),
+            "#undef RIOT_APPLICATION\n#include \"board.h\"\n",
+        )
+        .unwrap();
+
+        let cflags: Vec<String> = vec![
+            "-include".into(),
+            riotbuild.join("riotbuild.h").display().to_string(),
+            format!("-I{}", include.display()),
+            "-std=c11".into(),
+        ];
+        let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        let preprocessed = preprocess(
+            &cc,
+            &cflags,
+            &out_dir,
+            "riot-c2rust.h",
+            &out_dir.join("riot-c2rust.d"),
+        );
+        key(&preprocessed, &cflags, "c2rust", "")
+    }
+
+    #[test]
+    fn key_is_shared_between_applications() {
+        let root = std::env::temp_dir().join(format!("riot-sys-cache-test-{}", std::process::id()));
+        let hello_world = application_key(&root, "hello-world", "native");
+        let saul = application_key(&root, "saul", "native");
+        let other_board = application_key(&root, "hello-world", "microbit-v2");
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(hello_world, saul);
+        assert_ne!(hello_world, other_board);
+    }
+}
@@ -0,0 +1,427 @@
+//! Fix-ups to the C2Rust output
+//!
+//! The output of C2Rust is parsed into a syntax tree, and then run through the list of [PASSES].
+//! Each pass is a plain function on the parsed file that only depends on what is set in the
+//! [Context], so they can be run (and checked) in isolation on small snippets of C2Rust output.
+//!
+//! Working on the syntax tree (rather than on the text) keeps these from breaking silently when
+//! C2Rust changes its formatting.
+
+use syn::visit_mut::{self, VisitMut};
+use syn::{parse_quote, Item};
+
+/// Configuration for and results of the passes
+#[derive(Default)]
+pub struct Context {
+    /// Set if the C2Rust version still produces `llvm_asm!` style assembly as `asm!`
+    pub legacy_asm: bool,
+    /// Set if extern types are to be kept (requiring the nightly `extern_types` feature)
+    pub keep_extern_types: bool,
+    /// Set if `VaList` arguments are to be kept (requiring the nightly `c_variadic` feature)
+    pub keep_va_list: bool,
+    /// Names of the macro functions (without their `macro_` prefix), and whether they are const
+    pub macro_functions: Vec<(String, bool)>,
+
+    /// Extern types that were removed by [strip_extern_types]
+    pub extern_types: Vec<String>,
+    /// Names of functions, constants and statics in the output, as found by [collect_items]
+    pub items: Vec<String>,
+}
+
+/// A single transformation of the C2Rust output
+pub type Pass = fn(&mut syn::File, &mut Context);
+
+/// The passes applied by [process], in the order in which they run
+pub const PASSES: &[Pass] = &[
+    remove_libc_use,
+    legacy_asm,
+    intrinsics_replacements,
+    strip_extern_types,
+    replace_va_list,
+    function_signatures,
+    disable_use_everything,
+    collect_items,
+];
+
+/// Parse C2Rust output, run all [PASSES] on it, and produce the resulting code
+pub fn process(code: &str, context: &mut Context) -> syn::Result<String> {
+    let mut file = syn::parse_file(code)?;
+    for pass in PASSES {
+        pass(&mut file, context);
+    }
+    Ok(prettyplease::unparse(&file))
+}
+
+/// Remove the `use ::libc;` line; `libc` is provided by the including module
+pub fn remove_libc_use(file: &mut syn::File, _: &mut Context) {
+    file.items.retain(|item| {
+        !matches!(item, Item::Use(u) if matches!(&u.tree, syn::UseTree::Name(n) if n.ident == "libc"))
+    });
+}
+
+/// Old C2Rust still generate old-style ASM -- workaround for
+/// <https://github.com/immunant/c2rust/issues/306>
+pub fn legacy_asm(file: &mut syn::File, context: &mut Context) {
+    struct Visitor;
+    impl VisitMut for Visitor {
+        fn visit_macro_mut(&mut self, mac: &mut syn::Macro) {
+            if mac.path.is_ident("asm") {
+                mac.path = parse_quote!(llvm_asm);
+            }
+            visit_mut::visit_macro_mut(self, mac);
+        }
+    }
+
+    if context.legacy_asm {
+        Visitor.visit_file_mut(file);
+    }
+}
+
+/// Workaround for <https://github.com/immunant/c2rust/issues/372>
+pub fn intrinsics_replacements(file: &mut syn::File, _: &mut Context) {
+    struct Visitor;
+    impl VisitMut for Visitor {
+        fn visit_path_mut(&mut self, path: &mut syn::Path) {
+            let is_intrinsic = path.leading_colon.is_some()
+                && path.segments.len() > 2
+                && path.segments[0].ident == "core"
+                && path.segments[1].ident == "intrinsics";
+            if is_intrinsic {
+                let rest = path.segments.iter().skip(2);
+                *path = parse_quote!(crate::intrinsics_replacements::#(#rest)::*);
+            }
+            visit_mut::visit_path_mut(self, path);
+        }
+    }
+
+    Visitor.visit_file_mut(file);
+}
+
+/// Remove extern types (`extern "C" { pub type foo; }`), recording their names in the context so
+/// that replacements can be provided.
+///
+/// For documentation on why we do this, see include in src/inline.rs.
+pub fn strip_extern_types(file: &mut syn::File, context: &mut Context) {
+    if context.keep_extern_types {
+        return;
+    }
+
+    for item in file.items.iter_mut() {
+        if let Item::ForeignMod(foreign) = item {
+            foreign.items.retain(|foreign_item| match foreign_item {
+                syn::ForeignItem::Type(t) => {
+                    context.extern_types.push(t.ident.to_string());
+                    false
+                }
+                _ => true,
+            });
+        }
+    }
+}
+
+/// Make functions with `va_list` arguments uncallable
+///
+/// On 64-bit native, what gets emitted as vprintf(_, _, _: __builtin_va_list) gets emitted as
+/// vprintf(_, _, _: core::ffi::VaList), which is unsupported in stable -- but we don't use that
+/// function, it's just an unfortunate side effect of --preserve-unused-functions. This quick
+/// workaround enables building and ensures that the function is never called.
+///
+/// With the c-variadic feature, the nightly `c_variadic` feature is enabled instead, and the
+/// functions stay usable.
+pub fn replace_va_list(file: &mut syn::File, context: &mut Context) {
+    struct Visitor;
+    impl VisitMut for Visitor {
+        fn visit_type_mut(&mut self, ty: &mut syn::Type) {
+            if let syn::Type::Path(path) = ty {
+                let idents: Vec<_> = path.path.segments.iter().map(|s| &s.ident).collect();
+                if idents == ["core", "ffi", "VaList"] {
+                    *ty = parse_quote!(::core::convert::Infallible);
+                    return;
+                }
+            }
+            visit_mut::visit_type_mut(self, ty);
+        }
+
+        fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+            if let syn::Expr::MethodCall(call) = expr {
+                if call.method == "as_va_list" && call.args.is_empty() {
+                    *expr = (*call.receiver).clone();
+                }
+            }
+            visit_mut::visit_expr_mut(self, expr);
+        }
+    }
+
+    if !context.keep_va_list {
+        Visitor.visit_file_mut(file);
+    }
+}
+
+/// Replace the function declarations with ... usually something pub, but special considerations
+/// may apply
+pub fn function_signatures(file: &mut syn::File, context: &mut Context) {
+    for item in file.items.iter_mut() {
+        let function = match item {
+            Item::Fn(function) => function,
+            _ => continue,
+        };
+        let is_extern_c = match &function.sig.abi {
+            Some(abi) => abi.name.as_ref().map(|n| n.value() == "C").unwrap_or(true),
+            None => false,
+        };
+        if function.sig.unsafety.is_none() || !is_extern_c {
+            continue;
+        }
+
+        let name = function.sig.ident.to_string();
+        let macro_details = name.strip_prefix("macro_").and_then(|macro_name| {
+            context
+                .macro_functions
+                .iter()
+                .find(|(name, _)| name == macro_name)
+        });
+        match (name.as_str(), macro_details) {
+            // used as a callback, therefore does need the extern "C" -- FIXME probably worth a RIOT issue
+            ("_evtimer_msg_handler" | "_evtimer_mbox_handler", _) => (),
+
+            // Assigned by CMSIS to the const that is being overridden and thus needs its original
+            // "C" type; see also riot-c2rust.h. (Actually using it would cause a linker error
+            // anyway).
+            ("__masked_builtin_arm_get_fpscr" | "__masked_builtin_arm_set_fpscr", _) => (),
+
+            // same problem but from C2Rust's --translate-const-macros
+            ("__NVIC_SetPriority", _) => (),
+
+            // As below (no need for extern), and they are const as declared ni the macro_functions
+            // list.
+            (_, Some((_, is_const))) => {
+                // No need to make them pub, they were never static
+                function.sig.abi = None;
+                // FIXME: These should be unsafe -- just because most of them are const doesn't
+                // necessrily mean they're safe (just the first few happened to be, but that's
+                // not this crate's place to assert)
+                if *is_const {
+                    function.sig.constness = Some(Default::default());
+                }
+            }
+
+            // C2Rust transpiles these into Rust with conflicting lifetimes, see
+            // https://github.com/immunant/c2rust/issues/309
+            //
+            // Simply disabling them here because they aren't used by any other inline code (and
+            // will, when the manual llvm_asm to asm changes are added to riot-sys, not have manual
+            // asm conversions on top of that).
+            ("__SMLALD" | "__SMLALDX" | "__SMLSLD" | "__SMLSLDX", _) => {
+                function.attrs.push(parse_quote!(#[cfg(c2rust_fixed_309)]));
+                function.sig.abi = None;
+                function.vis = parse_quote!(pub);
+            }
+
+            // The rest we don't need to call through the extern convention, but let's please make
+            // them pub to be usable
+            _ => {
+                function.sig.abi = None;
+                function.vis = parse_quote!(pub);
+            }
+        }
+    }
+}
+
+/// We don't even need that function (or its counterpart with the application's additions) any
+/// more, we just have that in there so that c2rust gives us its callees as functions. (In bindgen,
+/// we'd just list the functions we'd like to have).
+pub fn disable_use_everything(file: &mut syn::File, _: &mut Context) {
+    for item in file.items.iter_mut() {
+        if let Item::Fn(function) = item {
+            if function.sig.ident == "use_everything"
+                || function.sig.ident == "use_everything_extra"
+            {
+                function.attrs.push(parse_quote!(#[cfg(false)]));
+                function.vis = syn::Visibility::Inherited;
+            }
+        }
+    }
+}
+
+/// Record which functions, constants and statics are defined in the output
+///
+/// Declarations in `extern "C"` blocks are deliberately not recorded: `irq_disable` on native is
+/// visible as `extern "C" { fn irq_disable(); }`, and that should not make it go through C2Rust.
+pub fn collect_items(file: &mut syn::File, context: &mut Context) {
+    for item in file.items.iter() {
+        let ident = match item {
+            Item::Fn(function) => &function.sig.ident,
+            Item::Const(constant) => &constant.ident,
+            Item::Static(static_) => &static_.ident,
+            _ => continue,
+        };
+        context.items.push(ident.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a single pass on some code, and return the result formatted
+    fn run(pass: Pass, code: &str, context: &mut Context) -> String {
+        let mut file = syn::parse_file(code).expect("Test input is valid Rust");
+        pass(&mut file, context);
+        prettyplease::unparse(&file)
+    }
+
+    fn formatted(code: &str) -> String {
+        prettyplease::unparse(&syn::parse_file(code).expect("Test input is valid Rust"))
+    }
+
+    #[test]
+    fn intrinsics_are_replaced() {
+        let output = run(
+            intrinsics_replacements,
+            "unsafe fn f(x: *mut u32) -> u32 {
+                ::core::ptr::read_volatile(x);
+                ::core::intrinsics::atomic_xadd_seqcst(x, 1)
+            }",
+            &mut Context::default(),
+        );
+        assert_eq!(
+            output,
+            formatted(
+                "unsafe fn f(x: *mut u32) -> u32 {
+                    ::core::ptr::read_volatile(x);
+                    crate::intrinsics_replacements::atomic_xadd_seqcst(x, 1)
+                }"
+            )
+        );
+    }
+
+    #[test]
+    fn items_are_collected() {
+        let mut context = Context::default();
+        run(
+            collect_items,
+            "pub unsafe fn irq_is_in() -> bool { false }
+            pub const THREAD_PRIORITY_MAIN: u8 = 7;
+            pub static mut sched_num_threads: u8 = 0;
+            pub struct thread_t { pub priority: u8 }
+            extern \"C\" { fn irq_disable() -> u32; }",
+            &mut context,
+        );
+        assert_eq!(
+            context.items,
+            ["irq_is_in", "THREAD_PRIORITY_MAIN", "sched_num_threads"]
+        );
+    }
+
+    #[test]
+    fn extern_c_is_stripped() {
+        let mut context = Context {
+            macro_functions: vec![("MUTEX_INIT".to_string(), true)],
+            ..Context::default()
+        };
+        let output = run(
+            function_signatures,
+            "#[inline]
+            unsafe extern \"C\" fn mutex_trylock(mutex: *mut mutex_t) -> i32 { 0 }
+            unsafe extern \"C\" fn macro_MUTEX_INIT() -> mutex_t { mutex_t {} }
+            unsafe extern \"C\" fn _evtimer_msg_handler(event: *mut evtimer_event_t) {}
+            fn not_from_c() {}",
+            &mut context,
+        );
+        assert_eq!(
+            output,
+            formatted(
+                "#[inline]
+                pub unsafe fn mutex_trylock(mutex: *mut mutex_t) -> i32 { 0 }
+                const unsafe fn macro_MUTEX_INIT() -> mutex_t { mutex_t {} }
+                unsafe extern \"C\" fn _evtimer_msg_handler(event: *mut evtimer_event_t) {}
+                fn not_from_c() {}"
+            )
+        );
+    }
+
+    #[test]
+    fn extern_types_are_stripped() {
+        let mut context = Context::default();
+        let output = run(
+            strip_extern_types,
+            "extern \"C\" { pub type _reent; fn puts(s: *const u8) -> i32; }",
+            &mut context,
+        );
+        assert_eq!(
+            output,
+            formatted("extern \"C\" { fn puts(s: *const u8) -> i32; }")
+        );
+        assert_eq!(context.extern_types, ["_reent"]);
+
+        let mut context = Context {
+            keep_extern_types: true,
+            ..Context::default()
+        };
+        let code = "extern \"C\" { pub type _reent; }";
+        assert_eq!(run(strip_extern_types, code, &mut context), formatted(code));
+        assert!(context.extern_types.is_empty());
+    }
+
+    #[test]
+    fn va_list_is_replaced() {
+        let code = "unsafe fn vprintf(format: *const u8, mut __arg: ::core::ffi::VaList) -> i32 {
+            vfprintf(stdout, format, __arg.as_va_list())
+        }";
+        assert_eq!(
+            run(replace_va_list, code, &mut Context::default()),
+            formatted(
+                "unsafe fn vprintf(format: *const u8, mut __arg: ::core::convert::Infallible) -> i32 {
+                    vfprintf(stdout, format, __arg)
+                }"
+            )
+        );
+
+        let mut context = Context {
+            keep_va_list: true,
+            ..Context::default()
+        };
+        assert_eq!(run(replace_va_list, code, &mut context), formatted(code));
+    }
+
+    #[test]
+    fn libc_use_is_removed() {
+        assert_eq!(
+            run(
+                remove_libc_use,
+                "use ::libc; use core::ptr;",
+                &mut Context::default()
+            ),
+            formatted("use core::ptr;")
+        );
+    }
+
+    #[test]
+    fn legacy_asm_is_renamed() {
+        let code = "unsafe fn f() { asm!(\"nop\"); }";
+        assert_eq!(
+            run(legacy_asm, code, &mut Context::default()),
+            formatted(code)
+        );
+        let mut context = Context {
+            legacy_asm: true,
+            ..Context::default()
+        };
+        assert_eq!(
+            run(legacy_asm, code, &mut context),
+            formatted("unsafe fn f() { llvm_asm!(\"nop\"); }")
+        );
+    }
+
+    #[test]
+    fn use_everything_is_disabled() {
+        assert_eq!(
+            run(
+                disable_use_everything,
+                "pub unsafe fn use_everything() {} pub unsafe fn other() {}",
+                &mut Context::default()
+            ),
+            formatted("#[cfg(false)] unsafe fn use_everything() {} pub unsafe fn other() {}")
+        );
+    }
+}
@@ -0,0 +1,426 @@
+//! Detection of the capabilities of the installed C2Rust
+//!
+//! Version numbers don't tell the whole story (people install C2Rust from git, or use RIOT's
+//! fork), and neither does the `--help` output. Instead, C2Rust is run on tiny built-in headers,
+//! and the outcome decides which options are used and which workarounds are applied.
+//!
+//! As that takes a moment, the result is cached next to the build script's output, keyed on the
+//! hash of the C2Rust binaries.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Oldest C2Rust version riot-sys can work with
+///
+/// Versions that can not be parsed (eg. from builds of unusual forks) are not checked against
+/// this; the probes below still catch any missing capabilities.
+const MINIMUM_VERSION: (u32, u32) = (0, 15);
+
+/// What the installed C2Rust was found to be able to do
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Output of `c2rust --version`
+    pub version: String,
+    /// Argument by which C2Rust is told to translate const macros; its form differs between
+    /// versions up to 0.20 and later ones.
+    pub translate_const_macros_arg: String,
+    /// Set if inline assembly is still emitted in the pre-2021 `llvm_asm!` style (but named
+    /// `asm!`)
+    pub legacy_asm: bool,
+    /// If C11 atomics (through `stdatomic.h`) can be translated, the `core::intrinsics` functions
+    /// the translations of the usual atomic operations call; see [Capabilities::atomics_for] for
+    /// whether they are used
+    pub atomic_intrinsics: Option<Vec<String>>,
+    /// Directory (relative to the location of the compile_commands.json) in which the modules
+    /// created through `--emit-modules` are placed
+    pub module_dir: PathBuf,
+}
+
+impl Capabilities {
+    /// Whether C11 atomics are to be passed to C2Rust when building for the given architecture
+    /// (as in `target_arch`)
+    ///
+    /// The atomics in C2Rust's output are calls to `core::intrinsics`, which are replaced with
+    /// functions from `crate::intrinsics_replacements`; `replacements` is the source of that
+    /// module. Unless it provides all the intrinsics the probe ran into for the architecture, the
+    /// stdatomic.h workaround in riot-c2rust.h stays active even if C2Rust can handle atomics.
+    pub fn atomics_for(&self, target_arch: &str, replacements: &str) -> bool {
+        let used = match &self.atomic_intrinsics {
+            Some(used) => used,
+            None => return false,
+        };
+        let provided = provided_intrinsics(replacements, target_arch);
+        used.iter().all(|u| provided.contains(u))
+    }
+}
+
+/// Names of the functions in the source of the `intrinsics_replacements` module that are
+/// available on the architecture
+///
+/// Functions are available unless they have a `#[cfg(target_arch = "...")]` for a different
+/// architecture.
+pub fn provided_intrinsics(replacements: &str, target_arch: &str) -> Vec<String> {
+    let file = syn::parse_file(replacements).expect("intrinsics_replacements.rs is valid Rust");
+    file.items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Fn(f) => Some(f),
+            _ => None,
+        })
+        .filter(|f| {
+            f.attrs.iter().all(|attr| {
+                let cfg_arch = attr
+                    .path()
+                    .is_ident("cfg")
+                    .then(|| attr.parse_args::<syn::MetaNameValue>().ok())
+                    .flatten()
+                    .filter(|nv| nv.path.is_ident("target_arch"));
+                match cfg_arch.map(|nv| nv.value) {
+                    Some(syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(arch),
+                        ..
+                    })) => arch.value() == target_arch,
+                    _ => true,
+                }
+            })
+        })
+        .map(|f| f.sig.ident.to_string())
+        .collect()
+}
+
+/// Names of the `core::intrinsics` functions used in C2Rust output
+fn used_intrinsics(code: &str) -> Vec<String> {
+    let mut used: Vec<String> = code
+        .split("intrinsics::")
+        .skip(1)
+        .map(|rest| {
+            rest.chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect()
+        })
+        .filter(|name: &String| !name.is_empty())
+        .collect();
+    used.sort();
+    used.dedup();
+    used
+}
+
+/// Major and minor version from `c2rust --version` output (eg. `C2Rust 0.18.0`)
+fn parse_version(version: &str) -> Option<(u32, u32)> {
+    version.split_whitespace().find_map(|word| {
+        let mut parts = word.trim_start_matches('v').split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        Some((major, minor))
+    })
+}
+
+/// Find the binary that would be executed for a command name (unless it already is a path)
+pub fn find_binary(name: &str) -> Option<PathBuf> {
+    if name.contains(std::path::MAIN_SEPARATOR) {
+        return Some(PathBuf::from(name)).filter(|p| p.is_file());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|p| p.is_file())
+}
+
+/// Hash over the C2Rust binary and its `c2rust-transpile` sibling (which does the actual work)
+pub fn binary_hash(c2rust: &str) -> Option<String> {
+    let binary = find_binary(c2rust)?;
+    let mut hasher = Sha256::new();
+    hasher.update(std::fs::read(&binary).ok()?);
+    if let Some(transpile) = binary
+        .parent()
+        .map(|dir| dir.join("c2rust-transpile"))
+        .filter(|p| p.is_file())
+    {
+        hasher.update(std::fs::read(transpile).ok()?);
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// Determine the capabilities of the given C2Rust command, using a cached result from the
+/// directory if present
+///
+/// The hash is the C2Rust command's [binary_hash]; without it, no caching takes place.
+///
+/// Panics with an explanation if C2Rust lacks any capability riot-sys depends on.
+pub fn probe_cached(c2rust: &str, binary_hash: Option<&str>, cache_dir: &Path) -> Capabilities {
+    let cache_file =
+        binary_hash.map(|hash| cache_dir.join(format!("c2rust-capabilities-{}.json", hash)));
+
+    if let Some(cached) = cache_file
+        .as_ref()
+        .and_then(|f| std::fs::read(f).ok())
+        .and_then(|data| serde_json::from_slice(&data).ok())
+    {
+        return cached;
+    }
+
+    let capabilities = probe(c2rust, &cache_dir.join("c2rust-probe"));
+
+    if let Some(cache_file) = cache_file {
+        std::fs::write(
+            cache_file,
+            serde_json::to_vec_pretty(&capabilities).expect("Serialization does not fail"),
+        )
+        .expect("Failed to write C2Rust capabilities");
+    }
+
+    capabilities
+}
+
+/// Output of `c2rust --version`, or None if the command can not be run
+pub fn version(c2rust: &str) -> Option<String> {
+    let output = std::process::Command::new(c2rust)
+        .args(["--version"])
+        .output()
+        .ok()?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Run the probes in the given (scratch) directory
+pub fn probe(c2rust: &str, probe_dir: &Path) -> Capabilities {
+    let version = version(c2rust).expect("C2Rust version check did not complete");
+    if let Some(parsed) = parse_version(&version) {
+        if parsed < MINIMUM_VERSION {
+            missing(&version, "the options and output riot-sys expects");
+        }
+    }
+
+    let const_macros_code = "
+        #define RIOT_SYS_PROBE_CONST 42
+        int riot_sys_probe(void) { return RIOT_SYS_PROBE_CONST; }
+    ";
+    // The `=conservative` is required from 0.21 on, and unknown before
+    let (translate_const_macros_arg, (module_dir, _)) = [
+        "--translate-const-macros=conservative",
+        "--translate-const-macros",
+    ]
+    .iter()
+    .find_map(|arg| {
+        transpile(
+            c2rust,
+            probe_dir,
+            "riot_sys_probe_const",
+            const_macros_code,
+            &[arg],
+        )
+        .filter(|(_, output)| output.contains("RIOT_SYS_PROBE_CONST"))
+        .map(|result| (arg.to_string(), result))
+    })
+    .unwrap_or_else(|| {
+        missing(
+            &version,
+            "translation of const macros (--translate-const-macros)",
+        )
+    });
+
+    let asm_code = r#"
+        void riot_sys_probe(void) { __asm__ volatile ("nop" ::: "memory"); }
+    "#;
+    let (_, asm_output) = transpile(c2rust, probe_dir, "riot_sys_probe_asm", asm_code, &[])
+        .unwrap_or_else(|| missing(&version, "translation of inline assembly"));
+    // llvm_asm style places the options after colons, eg. `asm!("nop" : : : "memory" :
+    // "volatile")`
+    let legacy_asm = asm_output.contains("\"volatile\"");
+
+    // The operations RIOT's headers use, with the default and with relaxed ordering
+    let atomics_code = "
+        #include <stdatomic.h>
+        atomic_uint riot_sys_probe_atomic;
+        unsigned riot_sys_probe(unsigned x) {
+            unsigned expected = 0;
+            atomic_store(&riot_sys_probe_atomic, x);
+            atomic_store_explicit(&riot_sys_probe_atomic, x, memory_order_relaxed);
+            atomic_fetch_add(&riot_sys_probe_atomic, x);
+            atomic_fetch_add_explicit(&riot_sys_probe_atomic, x, memory_order_relaxed);
+            atomic_fetch_sub(&riot_sys_probe_atomic, x);
+            atomic_fetch_sub_explicit(&riot_sys_probe_atomic, x, memory_order_relaxed);
+            atomic_fetch_and(&riot_sys_probe_atomic, x);
+            atomic_fetch_and_explicit(&riot_sys_probe_atomic, x, memory_order_relaxed);
+            atomic_fetch_or(&riot_sys_probe_atomic, x);
+            atomic_fetch_or_explicit(&riot_sys_probe_atomic, x, memory_order_relaxed);
+            atomic_exchange(&riot_sys_probe_atomic, x);
+            atomic_compare_exchange_strong(&riot_sys_probe_atomic, &expected, x);
+            atomic_load_explicit(&riot_sys_probe_atomic, memory_order_relaxed);
+            return atomic_load(&riot_sys_probe_atomic);
+        }
+    ";
+    let atomic_intrinsics = transpile(
+        c2rust,
+        probe_dir,
+        "riot_sys_probe_atomics",
+        atomics_code,
+        &[],
+    )
+    .map(|(_, output)| used_intrinsics(&output));
+
+    Capabilities {
+        version,
+        translate_const_macros_arg,
+        legacy_asm,
+        atomic_intrinsics,
+        module_dir,
+    }
+}
+
+fn missing(version: &str, feature: &str) -> ! {
+    panic!(
+        "The installed C2Rust ({}) does not support {}, which riot-sys depends on. Please install \
+         C2Rust {}.{} or later (set C2RUST to use a C2Rust binary that is not in the PATH).",
+        version, feature, MINIMUM_VERSION.0, MINIMUM_VERSION.1
+    );
+}
+
+/// Run C2Rust on a single probe header
+///
+/// On success, this returns the directory the module was placed in (relative to `dir`) and the
+/// produced Rust code.
+fn transpile(
+    c2rust: &str,
+    dir: &Path,
+    name: &str,
+    code: &str,
+    extra_args: &[&str],
+) -> Option<(PathBuf, String)> {
+    let dir = dir.join(name);
+    // Clear out leftovers from an earlier probe
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).expect("Failed to create C2Rust probe directory");
+
+    let infile = format!("{}.h", name);
+    std::fs::write(dir.join(&infile), code).expect("Failed to write C2Rust probe header");
+    let compile_commands = serde_json::json!([{
+        "arguments": ["any-cc", &infile],
+        "directory": &dir,
+        "file": &infile,
+    }]);
+    let compile_commands_name = dir.join("compile_commands.json");
+    std::fs::write(
+        &compile_commands_name,
+        serde_json::to_vec_pretty(&compile_commands).expect("Serialization does not fail"),
+    )
+    .expect("Failed to write C2Rust probe compile_commands.json");
+
+    let output = std::process::Command::new(c2rust)
+        .arg("transpile")
+        .arg(&compile_commands_name)
+        .args([
+            "--emit-modules",
+            "--emit-no-std",
+            "--overwrite-existing",
+            "--fail-on-error",
+        ])
+        .args(extra_args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let module = find_file(&dir, &format!("{}.rs", name))?;
+    let code = std::fs::read_to_string(&module).ok()?;
+    let module_dir = module
+        .parent()
+        .and_then(|parent| parent.strip_prefix(&dir).ok())
+        .expect("Found file is inside the probe directory")
+        .to_path_buf();
+    Some((module_dir, code))
+}
+
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    let entries: Vec<_> = std::fs::read_dir(dir).ok()?.flatten().collect();
+    if let Some(found) = entries.iter().find(|e| e.file_name() == name) {
+        return Some(found.path());
+    }
+    entries
+        .iter()
+        .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+        .find_map(|e| find_file(&e.path(), name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPLACEMENTS: &str = r#"
+        #[cfg(target_arch = "riscv32")]
+        pub(crate) fn atomic_or_relaxed(dst: *mut u32, src: u32) -> u32 { 0 }
+        #[cfg(target_arch = "arm")]
+        pub(crate) fn atomic_load_seqcst(src: *const u32) -> u32 { 0 }
+        pub(crate) fn atomic_fence_seqcst() {}
+    "#;
+
+    fn capabilities(atomic_intrinsics: Option<&[&str]>) -> Capabilities {
+        Capabilities {
+            version: "C2Rust 0.20.0".to_string(),
+            translate_const_macros_arg: "--translate-const-macros".to_string(),
+            legacy_asm: false,
+            atomic_intrinsics: atomic_intrinsics
+                .map(|names| names.iter().map(|n| n.to_string()).collect()),
+            module_dir: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn intrinsics_are_provided_per_architecture() {
+        for (arch, expected) in [
+            ("riscv32", &["atomic_or_relaxed", "atomic_fence_seqcst"][..]),
+            ("arm", &["atomic_load_seqcst", "atomic_fence_seqcst"]),
+            ("x86", &["atomic_fence_seqcst"]),
+        ] {
+            assert_eq!(
+                provided_intrinsics(REPLACEMENTS, arch),
+                expected,
+                "{}",
+                arch
+            );
+        }
+    }
+
+    #[test]
+    fn intrinsics_are_found_in_output() {
+        let output = "
+            pub unsafe extern \"C\" fn riot_sys_probe(x: u32) -> u32 {
+                ::core::intrinsics::atomic_xadd_seqcst(&mut riot_sys_probe_atomic, x);
+                ::core::intrinsics::atomic_or_relaxed(&mut riot_sys_probe_atomic, x);
+                return ::core::intrinsics::atomic_load_seqcst(&mut riot_sys_probe_atomic);
+                ::core::intrinsics::atomic_or_relaxed(&mut riot_sys_probe_atomic, x);
+            }
+        ";
+        assert_eq!(
+            used_intrinsics(output),
+            [
+                "atomic_load_seqcst",
+                "atomic_or_relaxed",
+                "atomic_xadd_seqcst"
+            ]
+        );
+    }
+
+    #[test]
+    fn atomics_need_all_intrinsics() {
+        for (used, arch, expected) in [
+            (None, "riscv32", false),
+            (Some(&["atomic_or_relaxed"][..]), "riscv32", true),
+            (Some(&["atomic_or_relaxed"][..]), "arm", false),
+            (
+                Some(&["atomic_or_relaxed", "atomic_load_seqcst"][..]),
+                "riscv32",
+                false,
+            ),
+            (Some(&[][..]), "x86", true),
+        ] {
+            assert_eq!(
+                capabilities(used).atomics_for(arch, REPLACEMENTS),
+                expected,
+                "{:?} on {}",
+                used,
+                arch
+            );
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! Sizes of C's size types on the target
+//!
+//! Whether `size_t` and friends have the size of a pointer (and thus of `usize`) depends on the
+//! target and its libc. This is determined by compiling small test files with the C compiler and
+//! RIOT's CFLAGS, checking sizes with `_Static_assert` (only syntax checking is needed for that).
+//!
+//! The results are used both to make bindgen use `usize` and `isize` directly where that is
+//! correct, and to produce the aliases of `riot_sys::c_types`.
+
+use std::path::Path;
+
+/// Sizes (in bytes) of the types on the target
+pub struct CTypes {
+    pub pointer: u8,
+    pub size_t: u8,
+    pub ssize_t: u8,
+    pub ptrdiff_t: u8,
+    pub off_t: u8,
+}
+
+/// Find the size of a type among the usual sizes
+fn sizeof(cc: &str, cflags: &[String], out_path: &Path, ty: &str) -> Option<u8> {
+    let testfile = out_path.join("riot-sys-sizeof.c");
+    for size in [4, 8, 2] {
+        std::fs::write(
+            &testfile,
+            format!(
+                "#include <stddef.h>\n\
+                 #include <sys/types.h>\n\
+                 _Static_assert(sizeof({}) == {}, \"\");\n",
+                ty, size
+            ),
+        )
+        .expect("Failed to write riot-sys-sizeof.c");
+        let result = std::process::Command::new(cc)
+            .args(cflags)
+            .arg("-fsyntax-only")
+            .arg(&testfile)
+            .output()
+            .expect("Failed to run the C compiler to determine type sizes");
+        if result.status.success() {
+            return Some(size);
+        }
+    }
+    None
+}
+
+impl CTypes {
+    /// Determine the sizes, or `None` if any of them can not be determined (eg. because the libc
+    /// has no sys/types.h)
+    pub fn probe(cc: &str, cflags: &[String], out_path: &Path) -> Option<Self> {
+        Some(Self {
+            pointer: sizeof(cc, cflags, out_path, "void *")?,
+            size_t: sizeof(cc, cflags, out_path, "size_t")?,
+            ssize_t: sizeof(cc, cflags, out_path, "ssize_t")?,
+            ptrdiff_t: sizeof(cc, cflags, out_path, "ptrdiff_t")?,
+            off_t: sizeof(cc, cflags, out_path, "off_t")?,
+        })
+    }
+
+    /// Whether bindgen can represent `size_t`, `ssize_t` and `ptrdiff_t` as `usize` and `isize`
+    ///
+    /// Bindgen's setting covers all of them, so it can only be used if they all match.
+    pub fn size_t_is_usize(&self) -> bool {
+        self.size_t == self.pointer
+            && self.ssize_t == self.pointer
+            && self.ptrdiff_t == self.pointer
+    }
+
+    /// Content of the `c_types` module
+    pub fn generate(&self) -> String {
+        let unsigned = |size| {
+            if size == self.pointer {
+                "usize".to_string()
+            } else {
+                format!("u{}", size * 8)
+            }
+        };
+        let signed = |size| {
+            if size == self.pointer {
+                "isize".to_string()
+            } else {
+                format!("i{}", size * 8)
+            }
+        };
+        format!(
+            "/// C's `size_t` on the target\n\
+             pub type size_t = {};\n\
+             /// C's `ssize_t` on the target\n\
+             pub type ssize_t = {};\n\
+             /// C's `ptrdiff_t` on the target\n\
+             pub type ptrdiff_t = {};\n\
+             /// C's `off_t` on the target\n\
+             pub type off_t = i{};\n",
+            unsigned(self.size_t),
+            signed(self.ssize_t),
+            signed(self.ptrdiff_t),
+            self.off_t * 8,
+        )
+    }
+}
@@ -0,0 +1,94 @@
+//! The parts of the CFLAGS that dependent crates need to compile C code of their own
+//!
+//! `DEP_RIOT_SYS_CFLAGS` contains everything, including warnings, optimization and dependency
+//! file options that C glue code of other crates should not take over. The CFLAGS are therefore
+//! also exported in parts, each as a shell quoted list (like the CFLAGS):
+//!
+//! * `INCLUDES` and `SYSTEM_INCLUDES`: directories given with `-I` and `-isystem`
+//! * `INCLUDE_FILES`: files that are always included (`-include`, typically riotbuild.h)
+//! * `DEFINES`: defines given with `-D`, as `NAME` or `NAME=VALUE`
+//! * `ABI_CFLAGS`: everything else that influences how code is built, such as the target, `-m`
+//!   and `-f` options, and the C standard
+//!
+//! The riot-sys-build crate uses these to configure a C compiler.
+
+/// Options whose argument is a separate item
+const WITH_ARGUMENT: &[&str] = &[
+    "-I", "-isystem", "-include", "-D", "-U", "-target", "--target", "-imacros", "-MF", "-MT",
+    "-MQ", "-Xclang",
+];
+
+pub struct CflagsParts {
+    includes: Vec<String>,
+    system_includes: Vec<String>,
+    include_files: Vec<String>,
+    defines: Vec<String>,
+    abi_cflags: Vec<String>,
+}
+
+/// Whether an option only affects diagnostics, debug information, optimization or dependency
+/// tracking
+fn is_local(option: &str) -> bool {
+    option.starts_with("-W")
+        || option.starts_with("-O")
+        || option.starts_with("-g")
+        || option.starts_with("-M")
+        || option.starts_with("-fdiagnostics-")
+        || option.starts_with("-fmessage-length")
+        || option == "-pedantic"
+        || option == "-pedantic-errors"
+}
+
+impl CflagsParts {
+    pub fn split(cflags: &[String]) -> Self {
+        let mut parts = Self {
+            includes: vec![],
+            system_includes: vec![],
+            include_files: vec![],
+            defines: vec![],
+            abi_cflags: vec![],
+        };
+
+        let mut args = cflags.iter();
+        while let Some(arg) = args.next() {
+            let (option, value) = match WITH_ARGUMENT.iter().find(|o| arg.starts_with(**o)) {
+                Some(&option) if arg.len() == option.len() => match args.next() {
+                    Some(value) => (option, Some(value.as_str())),
+                    None => break,
+                },
+                // -Isomething, -DFOO, but not -includesomething or -isystemsomething, which are
+                // valid but unusual spellings
+                Some(&option) if option.len() == 2 => (option, Some(&arg[2..])),
+                _ => (arg.as_str(), None),
+            };
+            match (option, value) {
+                ("-I", Some(dir)) => parts.includes.push(dir.to_string()),
+                ("-isystem", Some(dir)) => parts.system_includes.push(dir.to_string()),
+                ("-include", Some(file)) => parts.include_files.push(file.to_string()),
+                ("-D", Some(define)) => parts.defines.push(define.to_string()),
+                (option, _) if is_local(option) => (),
+                (option, Some(value)) => {
+                    parts.abi_cflags.push(option.to_string());
+                    parts.abi_cflags.push(value.to_string());
+                }
+                (option, None) => parts.abi_cflags.push(option.to_string()),
+            }
+        }
+        parts
+    }
+
+    /// Lines to pass to Cargo
+    pub fn cargo_lines(&self) -> Vec<String> {
+        let join = |list: &[String]| {
+            shlex::try_join(list.iter().map(|s| s.as_str()))
+                .expect("Input is not expected to contain NUL characters")
+        };
+        vec![
+            format!("INCLUDES={}", join(&self.includes)),
+            format!("SYSTEM_INCLUDES={}", join(&self.system_includes)),
+            format!("INCLUDE_FILES={}", join(&self.include_files)),
+            format!("DEFINES={}", join(&self.defines)),
+            format!("ABI_CFLAGS={}", join(&self.abi_cflags)),
+        ]
+    }
+}
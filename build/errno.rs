@@ -0,0 +1,231 @@
+//! Error numbers of the target's libc
+//!
+//! The errno values are taken from the libc's errno.h as seen by the C compiler with RIOT's
+//! CFLAGS (listing all defines with `-dM`), so they cover exactly what the target's libc defines,
+//! independent of which of them bindgen happens to pick up. They are placed in the `Errno` type of
+//! `riot_sys::errno`.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Find the names and values of all errno values
+///
+/// The result is sorted by value (and by name among aliases).
+pub fn find(cc: &str, cflags: &[String], out_path: &Path) -> Vec<(String, i32)> {
+    let header = out_path.join("riot-sys-errno.h");
+    std::fs::write(&header, "#include <errno.h>\n").expect("Failed to write riot-sys-errno.h");
+    let defines = std::process::Command::new(cc)
+        .args(cflags)
+        .args(["-E", "-dM"])
+        .arg(&header)
+        .output()
+        .expect("Failed to run the C preprocessor on errno.h");
+    if !defines.status.success() {
+        panic!(
+            "Preprocessing errno.h failed:\n{}",
+            String::from_utf8_lossy(&defines.stderr)
+        );
+    }
+    parse(&String::from_utf8_lossy(&defines.stdout))
+}
+
+/// Pick the errno values from the preprocessor's list of defines (the output of `-dM`)
+///
+/// The result is sorted by value (and by name among aliases).
+fn parse(defines: &str) -> Vec<(String, i32)> {
+    // Lines look like `#define ENOENT 2`; some are aliases like `#define EWOULDBLOCK EAGAIN`
+    let definitions: HashMap<&str, &str> = defines
+        .lines()
+        .filter_map(|line| line.strip_prefix("#define "))
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(name, _)| is_errno_name(name))
+        .map(|(name, value)| (name, value.trim()))
+        .collect();
+
+    let mut errnos: Vec<(String, i32)> = definitions
+        .keys()
+        .filter_map(|name| Some((name.to_string(), resolve(&definitions, name, 0)?)))
+        .collect();
+    errnos.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+    errnos
+}
+
+/// Whether this is a name that looks like an errno value, eg. `ENOENT` (but not `E` or `E_FOO`)
+fn is_errno_name(name: &str) -> bool {
+    name.len() > 1
+        && name.starts_with('E')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+}
+
+/// The value of a positive integer literal like `2`, `(2)` or `0x2`
+///
+/// Anything else (expressions, negative numbers like EOF's `(-1)`) is not an errno value.
+fn integer_literal(value: &str) -> Option<i32> {
+    let value = value.trim_start_matches('(').trim_end_matches(')').trim();
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) if hex.chars().all(|c| c.is_ascii_hexdigit()) => i32::from_str_radix(hex, 16),
+        None if value.chars().all(|c| c.is_ascii_digit()) => value.parse(),
+        _ => return None,
+    };
+    parsed.ok().filter(|v| *v > 0)
+}
+
+/// The value of an errno name, which is either an integer literal or an alias of another errno
+/// name
+fn resolve(definitions: &HashMap<&str, &str>, name: &str, depth: usize) -> Option<i32> {
+    let value = definitions.get(name)?;
+    if let Some(value) = integer_literal(value) {
+        return Some(value);
+    }
+    // Aliases of aliases are rare, but cycles must not make this loop forever
+    if is_errno_name(value) && depth < 8 {
+        return resolve(definitions, value, depth + 1);
+    }
+    None
+}
+
+/// Produce the content of the `errno` module
+///
+/// If `tiny_strerror` is available, it is used to display the errors; otherwise, their names are
+/// shown.
+pub fn generate(errnos: &[(String, i32)], tiny_strerror: bool) -> String {
+    let mut consts = String::new();
+    let mut names = String::new();
+    let mut previous = None;
+    for (name, value) in errnos {
+        consts.push_str(&format!(
+            "    /// `{name}`\n    pub const {name}: Self = Self({value});\n"
+        ));
+        // Aliases come right after the name they alias, as errnos is sorted
+        if previous != Some(value) {
+            names.push_str(&format!("            {value} => Some(\"{name}\"),\n"));
+        }
+        previous = Some(value);
+    }
+
+    let display = if tiny_strerror {
+        "        // SAFETY: tiny_strerror accepts any number, and always returns a static string
+        let message = unsafe { core::ffi::CStr::from_ptr(crate::tiny_strerror(self.0)) };
+        match message.to_str() {
+            Ok(message) => f.write_str(message),
+            Err(_) => core::fmt::Debug::fmt(self, f),
+        }"
+    } else {
+        "        core::fmt::Debug::fmt(self, f)"
+    };
+
+    format!(
+        "impl Errno {{
+{consts}
+    /// Name of the error number, eg. `\"ENOENT\"`
+    ///
+    /// Where several names share a number, the first in alphabetic order is given.
+    pub const fn name(self) -> Option<&'static str> {{
+        match self.0 {{
+{names}            _ => None,
+        }}
+    }}
+}}
+
+impl core::fmt::Display for Errno {{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {{
+{display}
+    }}
+}}
+"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errno_names() {
+        for (name, expected) in [
+            ("ENOENT", true),
+            ("E2BIG", true),
+            ("E", false),
+            ("E_FOO", false),
+            ("EOF", true),
+            ("Eabc", false),
+            ("ENOENT_", false),
+            ("NOENT", false),
+        ] {
+            assert_eq!(is_errno_name(name), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn integer_literals() {
+        for (value, expected) in [
+            ("2", Some(2)),
+            ("(2)", Some(2)),
+            ("0x2a", Some(42)),
+            ("0X2A", Some(42)),
+            ("( 7 )", Some(7)),
+            ("0", None),
+            ("(-1)", None),
+            ("-1", None),
+            ("EAGAIN", None),
+            ("(1 + 2)", None),
+            ("0x", None),
+            ("99999999999", None),
+        ] {
+            assert_eq!(integer_literal(value), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn resolving() {
+        let definitions: HashMap<&str, &str> = [
+            ("EAGAIN", "11"),
+            ("EWOULDBLOCK", "EAGAIN"),
+            ("EALIAS", "EWOULDBLOCK"),
+            ("ELOOP1", "ELOOP2"),
+            ("ELOOP2", "ELOOP1"),
+            ("EMISSING", "EUNDEFINED"),
+            ("EEXPR", "(EAGAIN + 1)"),
+        ]
+        .into_iter()
+        .collect();
+        for (name, expected) in [
+            ("EAGAIN", Some(11)),
+            ("EWOULDBLOCK", Some(11)),
+            ("EALIAS", Some(11)),
+            ("ELOOP1", None),
+            ("EMISSING", None),
+            ("EEXPR", None),
+            ("EUNDEFINED", None),
+        ] {
+            assert_eq!(resolve(&definitions, name, 0), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn parse_defines() {
+        let defines = "#define _ERRNO_H_ \n\
+                       #define ENOENT 2\n\
+                       #define EAGAIN 11\n\
+                       #define EWOULDBLOCK EAGAIN\n\
+                       #define EOF (-1)\n\
+                       #define EPERM 1\n\
+                       #define errno (*__errno())\n\
+                       #define __ELASTERROR 2000\n";
+        assert_eq!(
+            parse(defines),
+            [
+                ("EPERM", 1),
+                ("ENOENT", 2),
+                ("EAGAIN", 11),
+                ("EWOULDBLOCK", 11)
+            ]
+            .map(|(name, value)| (name.to_string(), value))
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Bindings for modules from RIOT's EXTERNAL_MODULE_DIRS
+//!
+//! External modules are recognized by their include directories in the CFLAGS (which RIOT's build
+//! system only adds for modules that are in use). All headers in there are processed by bindgen
+//! and C2Rust just like riot-headers.h, but the resulting items are placed in a Rust module of
+//! their own (`riot_sys::external::<module>`), so that they can not collide with RIOT's items.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// An external module in use
+pub struct ExternalModule {
+    /// Name of the module, usable as a Rust identifier
+    pub name: String,
+    /// The module's directory inside one of the EXTERNAL_MODULE_DIRS
+    pub dir: PathBuf,
+    /// Include directories of the module
+    pub include_dirs: Vec<PathBuf>,
+    /// All header files in the include directories
+    pub headers: Vec<PathBuf>,
+}
+
+/// Find the external modules in the given (whitespace separated) EXTERNAL_MODULE_DIRS that have
+/// include directories in the CFLAGS
+pub fn find(cflags: &[String], external_module_dirs: &str) -> Vec<ExternalModule> {
+    let external_module_dirs: Vec<PathBuf> = external_module_dirs
+        .split_whitespace()
+        .map(|dir| PathBuf::from(dir.trim_end_matches('/')))
+        .collect();
+
+    let mut include_dirs = vec![];
+    let mut args = cflags.iter();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("-I") {
+            Some("") => include_dirs.extend(args.next().map(|dir| dir.as_str())),
+            Some(dir) => include_dirs.push(dir),
+            None => (),
+        }
+    }
+
+    let mut modules: Vec<ExternalModule> = vec![];
+    for include_dir in include_dirs {
+        let include_dir = PathBuf::from(include_dir);
+        // The module is the directory right inside the external module directory
+        let module_dir = external_module_dirs.iter().find_map(|base| {
+            let mut components = include_dir.strip_prefix(base).ok()?.components();
+            Some(base.join(components.next()?))
+        });
+        let module_dir = match module_dir {
+            Some(module_dir) => module_dir,
+            None => continue,
+        };
+
+        let module = match modules.iter_mut().find(|m| m.dir == module_dir) {
+            Some(module) => module,
+            None => {
+                let name = module_name(&module_dir);
+                if let Some(other) = modules.iter().find(|m| m.name == name) {
+                    panic!(
+                        "External modules {} and {} would both be riot_sys::external::{}; \
+                         please rename one of them.",
+                        other.dir.display(),
+                        module_dir.display(),
+                        name
+                    );
+                }
+                modules.push(ExternalModule {
+                    name,
+                    dir: module_dir,
+                    include_dirs: vec![],
+                    headers: vec![],
+                });
+                modules.last_mut().expect("Just pushed")
+            }
+        };
+        if !module.include_dirs.contains(&include_dir) {
+            find_headers(&include_dir, &mut module.headers);
+            module.include_dirs.push(include_dir);
+        }
+    }
+
+    for module in modules.iter_mut() {
+        module.headers.sort();
+        module.headers.dedup();
+    }
+    modules
+}
+
+/// The Rust module name for an external module directory
+///
+/// Characters that can not be in identifiers (typically `-`) are replaced with underscores; names
+/// that are still not usable (keywords, or ones starting with a digit) are rejected.
+fn module_name(module_dir: &Path) -> String {
+    let dir_name = module_dir
+        .file_name()
+        .expect("Path was built from a component")
+        .to_string_lossy();
+    let name = dir_name.replace(|c: char| !(c.is_ascii_alphanumeric() || c == '_'), "_");
+    if syn::parse_str::<syn::Ident>(&name).is_err() {
+        panic!(
+            "External module {} can not be made available as riot_sys::external::{}, as that is \
+             not a valid Rust identifier (eg. a keyword, or starting with a digit); please rename \
+             the module's directory.",
+            module_dir.display(),
+            name
+        );
+    }
+    name
+}
+
+/// Names of all the types in the main bindings, to be removed from the module's bindings through
+/// the bindgen passes' blocked_types
+///
+/// The module's bindings `use crate::*`, so those need not be defined again; any other types the
+/// module's headers refer to (eg. RIOT types that are not in the main bindings because the
+/// application restricted them) stay along with the module's own items.
+pub fn main_types(main_bindings: &str) -> HashSet<String> {
+    let file = syn::parse_file(main_bindings)
+        .expect("Main bindings were just parsed by the bindgen passes");
+    file.items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Struct(i) => Some(&i.ident),
+            syn::Item::Union(i) => Some(&i.ident),
+            syn::Item::Enum(i) => Some(&i.ident),
+            syn::Item::Type(i) => Some(&i.ident),
+            _ => None,
+        })
+        .map(|ident| ident.to_string())
+        // Names of anonymous types are counted per bindgen run, and thus mean something else here
+        .filter(|name| !name.contains("_bindgen_ty_"))
+        .collect()
+}
+
+fn find_headers(dir: &Path, headers: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_headers(&path, headers);
+        } else if path.extension().map(|e| e == "h").unwrap_or(false) {
+            headers.push(path);
+        }
+    }
+}
+
+impl ExternalModule {
+    /// Content of a header file that includes all the module's headers
+    pub fn header(&self) -> String {
+        self.headers
+            .iter()
+            .map(|h| format!("#include \"{}\"\n", h.display()))
+            .collect()
+    }
+
+    /// Pattern for bindgen's allowlist_file that matches all the module's headers
+    pub fn file_pattern(&self) -> String {
+        let dirs: Vec<String> = self
+            .include_dirs
+            .iter()
+            .map(|d| escape_regex(&d.display().to_string()))
+            .collect();
+        format!("({})/.*", dirs.join("|"))
+    }
+}
+
+fn escape_regex(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,264 @@
+//! Building from previously generated bindings
+//!
+//! Generating the bindings needs libclang, C2Rust and a RIOT build environment. Where those are
+//! not available (eg. on docs.rs, or in CI jobs that only check downstream crates), the generated
+//! files can be taken from a directory instead:
+//!
+//! * A build with `RIOT_SYS_SAVE_PREGENERATED_DIR` set stores the generated files in that
+//!   directory, along with a `manifest.json` that contains a hash of the configuration and all
+//!   the information that the build script passes on to Cargo (cfgs, markers, `DEP_RIOT_SYS_...`
+//!   variables).
+//! * A build with `RIOT_SYS_PREGENERATED_DIR` set loads everything from that directory and skips
+//!   generation entirely. If a RIOT configuration is available, it needs to match the stored
+//!   hash. When building on docs.rs (`DOCS_RS` is set), a `pregenerated` directory in the crate
+//!   is used if present. It is not part of the repository; to have it on docs.rs, it is created
+//!   (with `RIOT_SYS_SAVE_PREGENERATED_DIR=$PWD/pregenerated`) before running `cargo publish`,
+//!   which then packages it along with the rest of the crate.
+//!
+//! The configuration hash covers the CC and CFLAGS, the crate's features and version, the
+//! [CONFIG_VARIABLES] (and the contents of the files named in them), the C2Rust version, and the
+//! contents of the RIOT headers as seen through the preprocessor. It is only computed when
+//! bindings are saved or loaded, as it takes an extra run of the preprocessor.
+//!
+//! Shims for static inline functions (see the `static-inline-shims` feature) are not stored;
+//! their functions are declared, but can not be linked in builds from pregenerated bindings.
+
+use sha2::{Digest, Sha256};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Generated files in OUT_DIR that are stored, in addition to the `external_*.rs` files of
+/// external modules
+pub const FILES: &[&str] = &[
+    "bindings.rs",
+    "enums.rs",
+    "flags.rs",
+    "errno.rs",
+    "c_types.rs",
+    "riot_version.rs",
+    "external.rs",
+    "riot_c2rust_replaced.rs",
+    "pubtype_replacements.rs",
+    "toplevel_from_inline.rs",
+];
+
+const MANIFEST: &str = "manifest.json";
+
+/// Stands in for OUT_DIR in stored `cargo:` lines (eg. in BINDGEN_OUTPUT_FILE)
+const OUT_DIR_PLACEHOLDER: &str = "${OUT_DIR}";
+
+/// Environment variables (besides the CFLAGS and features) that influence the generated files
+const CONFIG_VARIABLES: &[&str] = &[
+    "RIOT_SYS_EXTRA_HEADERS",
+    "RIOT_SYS_EXTRA_USE",
+    "RIOT_SYS_BINDGEN_ALLOWLIST",
+    "RIOT_SYS_BINDGEN_BLOCKLIST",
+    "RIOT_SYS_BINDGEN_CONFIG",
+    "RIOT_SYS_BINDGEN_USED_ITEMS",
+    "EXTERNAL_MODULE_DIRS",
+    "RIOT_FEATURES_USED",
+    "RIOT_FEATURES_PROVIDED",
+];
+
+/// Those of the [CONFIG_VARIABLES] that name files whose contents influence the generated files
+const CONFIG_FILE_VARIABLES: &[&str] = &["RIOT_SYS_BINDGEN_CONFIG", "RIOT_SYS_BINDGEN_USED_ITEMS"];
+
+/// `cargo:` lines emitted through [emit]
+static EMITTED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Manifest {
+    config_hash: String,
+    /// Lines to pass to Cargo, without their `cargo:` prefix
+    cargo: Vec<String>,
+    files: Vec<String>,
+}
+
+/// Pass a line to Cargo (without the `cargo:` prefix), and record it for a manifest
+///
+/// This is used for everything that dependent crates or the crate itself use; `rerun-if` lines
+/// and warnings are printed directly.
+pub fn emit(line: String) {
+    println!("cargo:{}", line);
+    EMITTED
+        .lock()
+        .expect("Emitting does not panic while holding the lock")
+        .push(line);
+}
+
+/// The directory to load pregenerated bindings from, if any
+pub fn configured_dir() -> Option<PathBuf> {
+    println!("cargo:rerun-if-env-changed=RIOT_SYS_PREGENERATED_DIR");
+    println!("cargo:rerun-if-env-changed=DOCS_RS");
+    if let Some(dir) = env::var_os("RIOT_SYS_PREGENERATED_DIR") {
+        return Some(PathBuf::from(dir));
+    }
+    if env::var_os("DOCS_RS").is_some() {
+        let dir =
+            Path::new(&env::var("CARGO_MANIFEST_DIR").expect("Set by Cargo")).join("pregenerated");
+        if dir.join(MANIFEST).exists() {
+            return Some(dir);
+        }
+        println!(
+            "cargo:warning=Building on docs.rs, but this riot-sys package contains no \
+             pregenerated bindings; trying to generate them."
+        );
+    }
+    None
+}
+
+/// Hash over everything that influences the generated files
+///
+/// This takes both the CFLAGS as configured and as used for generation (see [riot_headers]).
+pub fn config_hash(cc: &str, cflags: &str, generation_cflags: &[String], out_dir: &Path) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(cc.as_bytes());
+    hasher.update([0]);
+    hasher.update(cflags.as_bytes());
+    hasher.update([0]);
+    let mut features: Vec<String> = env::vars()
+        .map(|(name, _)| name)
+        .filter(|name| name.starts_with("CARGO_FEATURE_"))
+        .collect();
+    features.sort();
+    for feature in features {
+        hasher.update(feature.as_bytes());
+        hasher.update([0]);
+    }
+    for variable in CONFIG_VARIABLES {
+        println!("cargo:rerun-if-env-changed={}", variable);
+        hasher.update(env::var(variable).unwrap_or_default().as_bytes());
+        hasher.update([0]);
+    }
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update([0]);
+
+    println!("cargo:rerun-if-env-changed=C2RUST");
+    let c2rust = env::var("C2RUST").unwrap_or_else(|_| "c2rust".to_string());
+    hasher.update(super::c2rust_probe::version(&c2rust).unwrap_or_default());
+    hasher.update([0]);
+
+    for variable in CONFIG_FILE_VARIABLES {
+        // The files' names are in the CONFIG_VARIABLES already
+        if let Some(content) = env::var_os(variable).and_then(|path| std::fs::read(path).ok()) {
+            hasher.update(content);
+        }
+        hasher.update([0]);
+    }
+
+    hasher.update(riot_headers(cc, generation_cflags, out_dir));
+    format!("{:x}", hasher.finalize())
+}
+
+/// The RIOT headers (along with any RIOT_SYS_EXTRA_HEADERS) as preprocessed for the bindings,
+/// without line markers
+///
+/// The CFLAGS are the ones used for generation, in particular without `-MD` (which would
+/// otherwise leave a dependency file in the working directory). If the headers can not be
+/// preprocessed, nothing can be generated from them either; then, this is empty.
+fn riot_headers(cc: &str, cflags: &[String], out_dir: &Path) -> Vec<u8> {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").expect("Set by Cargo"));
+    let mut header = format!(
+        "#include \"{}\"\n",
+        manifest_dir.join("riot-bindgen.h").display()
+    );
+    for extra in env::var("RIOT_SYS_EXTRA_HEADERS")
+        .unwrap_or_default()
+        .split_whitespace()
+    {
+        header.push_str(&format!("#include \"{}\"\n", extra));
+    }
+    let header_path = out_dir.join("riot-sys-config-hash.h");
+    std::fs::write(&header_path, header).expect("Failed to write to OUT_DIR");
+
+    let output = std::process::Command::new(cc)
+        .current_dir(out_dir)
+        .args(cflags)
+        .args(["-E", "-P"])
+        .arg(&header_path)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => output.stdout,
+        _ => vec![],
+    }
+}
+
+/// Store the generated files from `out_dir` along with a manifest in `dir`
+pub fn save(dir: &Path, out_dir: &Path, config_hash: &str) {
+    std::fs::create_dir_all(dir)
+        .unwrap_or_else(|e| panic!("Failed to create {}: {}", dir.display(), e));
+
+    let mut files: Vec<String> = FILES.iter().map(|f| f.to_string()).collect();
+    for entry in std::fs::read_dir(out_dir).expect("Failed to list OUT_DIR") {
+        let name = entry
+            .expect("Failed to list OUT_DIR")
+            .file_name()
+            .to_string_lossy()
+            .into_owned();
+        if name.starts_with("external_") && name.ends_with(".rs") {
+            files.push(name);
+        }
+    }
+    files.retain(|f| out_dir.join(f).exists());
+    for file in files.iter() {
+        std::fs::copy(out_dir.join(file), dir.join(file))
+            .unwrap_or_else(|e| panic!("Failed to store {}: {}", file, e));
+    }
+
+    let out_dir = out_dir.display().to_string();
+    let cargo = EMITTED
+        .lock()
+        .expect("Emitting does not panic while holding the lock")
+        .iter()
+        .map(|line| line.replace(&out_dir, OUT_DIR_PLACEHOLDER))
+        .collect();
+    let manifest = Manifest {
+        config_hash: config_hash.to_string(),
+        cargo,
+        files,
+    };
+    let manifest = serde_json::to_string_pretty(&manifest).expect("Manifest is serializable");
+    std::fs::write(dir.join(MANIFEST), manifest).expect("Failed to write manifest.json");
+}
+
+/// Copy the files from `dir` into `out_dir`, and pass on the stored lines to Cargo
+///
+/// If a config hash is given, it needs to match the stored one.
+pub fn load(dir: &Path, out_dir: &Path, config_hash: Option<&str>) {
+    let manifest_path = dir.join(MANIFEST);
+    println!("cargo:rerun-if-changed={}", manifest_path.display());
+    let manifest = std::fs::read_to_string(&manifest_path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", manifest_path.display(), e));
+    let manifest: Manifest = serde_json::from_str(&manifest)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {}", manifest_path.display(), e));
+
+    if let Some(config_hash) = config_hash {
+        if config_hash != manifest.config_hash {
+            panic!(
+                "The pregenerated bindings in {} were generated for a different configuration \
+                 (different CFLAGS, features, RIOT headers, C2Rust or riot-sys version). \
+                 Regenerate them with RIOT_SYS_SAVE_PREGENERATED_DIR, or unset \
+                 RIOT_SYS_PREGENERATED_DIR.",
+                dir.display()
+            );
+        }
+    }
+
+    for file in manifest.files.iter() {
+        println!("cargo:rerun-if-changed={}", dir.join(file).display());
+        std::fs::copy(dir.join(file), out_dir.join(file))
+            .unwrap_or_else(|e| panic!("Failed to load pregenerated {}: {}", file, e));
+    }
+    // Files that were not generated in that configuration are still included
+    for file in FILES {
+        if !manifest.files.iter().any(|f| f == file) {
+            std::fs::write(out_dir.join(file), "").expect("Failed to write to OUT_DIR");
+        }
+    }
+
+    let out_dir = out_dir.display().to_string();
+    for line in manifest.cargo {
+        println!("cargo:{}", line.replace(OUT_DIR_PLACEHOLDER, &out_dir));
+    }
+}
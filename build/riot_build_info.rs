@@ -0,0 +1,110 @@
+//! Variables of the RIOT build, when built through RIOT-rs
+//!
+//! RIOT-rs builds RIOT through its riot-build crate, which exports the build directory as
+//! `DEP_RIOT_BUILD_DIR`. Variables of that build (USEMODULE, FEATURES_USED, BOARD, ...) are read
+//! from a JSON file written by the RIOT build: the one given in `DEP_RIOT_BUILD_INFO_JSON`, or
+//! `riot-build-info.json` in the build directory. It contains an object that maps variable names
+//! to their values, given either as strings or as lists of strings, eg.
+//!
+//! ```json
+//! {"BOARD": "microbit-v2", "USEMODULE": ["core", "ztimer"], "FEATURES_USED": "periph_gpio"}
+//! ```
+//!
+//! Variables that are not in the file (or all, if there is no such file) are queried from make
+//! through `info-debug-variable-...`, which is slow and depends on the environment, so it is only
+//! a fallback.
+
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const FILE_NAME: &str = "riot-build-info.json";
+
+pub struct BuildInfo {
+    builddir: String,
+    /// Variables from the JSON file, with lists joined by spaces
+    variables: HashMap<String, String>,
+}
+
+impl BuildInfo {
+    pub fn load() -> Self {
+        println!("cargo:rerun-if-env-changed=DEP_RIOT_BUILD_DIR");
+        println!("cargo:rerun-if-env-changed=DEP_RIOT_BUILD_INFO_JSON");
+        let builddir = env::var("DEP_RIOT_BUILD_DIR").expect("DEP_RIOT_BUILD_DIR unset?");
+        let path = env::var_os("DEP_RIOT_BUILD_INFO_JSON")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Path::new(&builddir).join(FILE_NAME));
+
+        let mut variables = HashMap::new();
+        if path.exists() {
+            println!("cargo:rerun-if-changed={}", path.display());
+            let file = std::fs::File::open(&path)
+                .unwrap_or_else(|e| panic!("Failed to open {}: {}", path.display(), e));
+            let parsed: HashMap<String, serde_json::Value> = serde_json::from_reader(file)
+                .unwrap_or_else(|e| panic!("Failed to parse {}: {}", path.display(), e));
+            for (name, value) in parsed {
+                let value = match value {
+                    serde_json::Value::String(s) => s,
+                    serde_json::Value::Array(items) => items
+                        .iter()
+                        .map(|i| {
+                            i.as_str().unwrap_or_else(|| {
+                                panic!("Non-string item in {} in {}", name, path.display())
+                            })
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    _ => panic!(
+                        "{} in {} is neither a string nor a list",
+                        name,
+                        path.display()
+                    ),
+                };
+                variables.insert(name, value);
+            }
+        }
+
+        Self {
+            builddir,
+            variables,
+        }
+    }
+
+    /// The value of a variable of the RIOT build
+    pub fn get(&self, var: &str) -> String {
+        self.try_get(var).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// The value of a variable of the RIOT build, or a description of why make could not provide
+    /// it
+    pub fn try_get(&self, var: &str) -> Result<String, String> {
+        match self.variables.get(var) {
+            Some(value) => Ok(value.clone()),
+            None => query_make(&self.builddir, var),
+        }
+    }
+}
+
+/// Ask make for the value of a variable
+fn query_make(builddir: &str, var: &str) -> Result<String, String> {
+    let output = Command::new("make")
+        .env("WARNING_EXTERNAL_MODULE_DIRS", "0")
+        .arg("--no-print-directory")
+        .arg("-C")
+        .arg(builddir)
+        .arg("TOOLCHAIN=llvm")
+        .arg(format!("info-debug-variable-{}", var))
+        .output()
+        .expect("Failed to run make to query the RIOT build");
+    if !output.status.success() {
+        return Err(format!(
+            "Querying {} from the RIOT build in {} failed ({}):\n{}",
+            var,
+            builddir,
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().into())
+}
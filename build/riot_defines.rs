@@ -0,0 +1,101 @@
+//! Configuration data from RIOT's defines
+//!
+//! RIOT passes information about the build (board, CPU etc.) to C code as defines, either directly
+//! in the CFLAGS or through a riotbuild.h file included with `-include`. They are read from there
+//! before the defines are stripped from the CFLAGS, and exported as cfgs and to dependent crates.
+
+use std::collections::HashMap;
+
+/// All defines from the CFLAGS and from any file they `-include`, by name
+///
+/// Later definitions win, as they do in C.
+pub fn collect(cflags: &[String]) -> HashMap<String, String> {
+    let mut defines = HashMap::new();
+    let mut args = cflags.iter();
+    while let Some(arg) = args.next() {
+        if let Some(define) = arg.strip_prefix("-D") {
+            let define = match define {
+                "" => match args.next() {
+                    Some(define) => define.as_str(),
+                    None => break,
+                },
+                define => define,
+            };
+            let (name, value) = define.split_once('=').unwrap_or((define, "1"));
+            defines.insert(name.to_string(), value.to_string());
+        } else if arg == "-include" {
+            let file = match args.next() {
+                Some(file) => file,
+                None => break,
+            };
+            // Typically riotbuild.h; any other file is just read the same way
+            let content = match std::fs::read_to_string(file) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+            println!("cargo:rerun-if-changed={}", file);
+            for line in content.lines() {
+                let line = match line.trim().strip_prefix("#define ") {
+                    Some(line) => line.trim(),
+                    None => continue,
+                };
+                let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, "1"));
+                // Function-like macros are not configuration data
+                if name.contains('(') {
+                    continue;
+                }
+                defines.insert(name.to_string(), value.trim().to_string());
+            }
+        }
+    }
+    defines
+}
+
+/// The value of a define that holds a string, eg. `RIOT_BOARD` (`"native"`)
+pub fn string(defines: &HashMap<String, String>, name: &str) -> Option<String> {
+    let value = defines.get(name)?;
+    Some(
+        value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value)
+            .to_string(),
+    )
+}
+
+/// Names of all cfgs set by [config]
+pub const CFGS: &[&str] = &["riot_board", "riot_cpu", "riot_mcu", "riot_arch"];
+
+/// The configuration values that are exported, along with the name of their cfg
+///
+/// These are:
+///
+/// * `RIOT_BOARD` (eg. `"native"`) as `riot_board`
+/// * `RIOT_CPU` (eg. `"native"`) as `riot_cpu`
+/// * `RIOT_MCU` (eg. `MCU_NATIVE`, lowercased without the prefix) as `riot_mcu`
+/// * The `CPU_ARCH_...` define (eg. `CPU_ARCH_ARMV7M`, lowercased without the prefix) as
+///   `riot_arch`
+///
+/// Values that are not defined are left out.
+pub fn config(defines: &HashMap<String, String>) -> Vec<(&'static str, String)> {
+    let mut config = vec![];
+    if let Some(board) = string(defines, "RIOT_BOARD") {
+        config.push(("riot_board", board));
+    }
+    if let Some(cpu) = string(defines, "RIOT_CPU") {
+        config.push(("riot_cpu", cpu));
+    }
+    if let Some(mcu) = string(defines, "RIOT_MCU") {
+        let mcu = mcu.strip_prefix("MCU_").unwrap_or(&mcu).to_lowercase();
+        config.push(("riot_mcu", mcu));
+    }
+    let mut arches: Vec<&String> = defines
+        .keys()
+        .filter(|name| name.starts_with("CPU_ARCH_"))
+        .collect();
+    arches.sort();
+    if let Some(arch) = arches.first() {
+        config.push(("riot_arch", arch["CPU_ARCH_".len()..].to_lowercase()));
+    }
+    config
+}
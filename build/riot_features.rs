@@ -0,0 +1,94 @@
+//! RIOT's features (FEATURES_USED and FEATURES_PROVIDED)
+//!
+//! Features describe what a board and CPU can do (eg. `periph_gpio_irq`, `periph_rtc_ms`,
+//! `arch_32bit`), and which of that the application uses. Unlike modules, they do not show up in
+//! the CFLAGS, so they are taken from the RIOT build: from the `RIOT_FEATURES_USED` and
+//! `RIOT_FEATURES_PROVIDED` variables, or from the RIOT build information under riot-rs.
+//!
+//! All features are set as `riot_feature="..."` cfgs, and the used ones also as
+//! `riot_feature_used="..."`; dependent crates get them as `DEP_RIOT_SYS_FEATURES` and
+//! `DEP_RIOT_SYS_FEATURES_USED`.
+
+pub struct Features {
+    pub used: Vec<String>,
+    pub provided: Vec<String>,
+}
+
+impl Features {
+    /// Build from whitespace separated lists
+    pub fn new(used: &str, provided: &str) -> Self {
+        let split = |list: &str| {
+            let mut list: Vec<String> = list.split_whitespace().map(|f| f.to_string()).collect();
+            list.sort();
+            list.dedup();
+            list
+        };
+        Self {
+            used: split(used),
+            provided: split(provided),
+        }
+    }
+
+    #[cfg(not(feature = "riot-rs"))]
+    pub fn from_env() -> Self {
+        println!("cargo:rerun-if-env-changed=RIOT_FEATURES_USED");
+        println!("cargo:rerun-if-env-changed=RIOT_FEATURES_PROVIDED");
+        // Absent in builds from older RIOT versions; then no features are known
+        Self::new(
+            &std::env::var("RIOT_FEATURES_USED").unwrap_or_default(),
+            &std::env::var("RIOT_FEATURES_PROVIDED").unwrap_or_default(),
+        )
+    }
+
+    /// Read the features from the RIOT build
+    ///
+    /// Features only refine what is available, so if they can not be queried from make, the build
+    /// continues without them (with a warning).
+    #[cfg(feature = "riot-rs")]
+    pub fn from_build_info(build_info: &super::riot_build_info::BuildInfo) -> Self {
+        let list = |var: &str| {
+            build_info.try_get(var).unwrap_or_else(|e| {
+                println!(
+                    "cargo:warning={} is not available, continuing without any riot_feature cfgs \
+                     from it.",
+                    var
+                );
+                for line in e.lines() {
+                    println!("cargo:warning={}", line);
+                }
+                String::new()
+            })
+        };
+        Self::new(&list("FEATURES_USED"), &list("FEATURES_PROVIDED"))
+    }
+
+    /// Used features are usually provided too, but are included either way
+    fn all(&self) -> Vec<&str> {
+        let mut all: Vec<&str> = self
+            .used
+            .iter()
+            .chain(self.provided.iter())
+            .map(|f| f.as_str())
+            .collect();
+        all.sort();
+        all.dedup();
+        all
+    }
+}
+
+/// Lines to pass to Cargo
+pub fn cfgs(features: &Features) -> Vec<String> {
+    let mut lines = vec![
+        "rustc-check-cfg=cfg(riot_feature, values(any()))".to_string(),
+        "rustc-check-cfg=cfg(riot_feature_used, values(any()))".to_string(),
+    ];
+    for feature in features.all() {
+        lines.push(format!("rustc-cfg=riot_feature={:?}", feature));
+    }
+    for feature in features.used.iter() {
+        lines.push(format!("rustc-cfg=riot_feature_used={:?}", feature));
+    }
+    lines.push(format!("FEATURES={}", features.all().join(" ")));
+    lines.push(format!("FEATURES_USED={}", features.used.join(" ")));
+    lines
+}
@@ -0,0 +1,215 @@
+//! Version of RIOT that is built against
+//!
+//! The version is read from the `RIOT_VERSION` define (eg. `"2024.10-devel-123-gabcdef"`, or
+//! `"2024.04.1"`), or if that is unavailable, from `RIOT_VERSION_CODE` (eg.
+//! `RIOT_VERSION_NUM(2024,10,0,0)`).
+
+use std::collections::HashMap;
+
+/// Oldest release for which a `riot_version_at_least_...` cfg is emitted
+const OLDEST_RELEASE: (u16, u8) = (2022, 1);
+
+/// RIOT releases in every year
+const RELEASE_MONTHS: [u8; 4] = [1, 4, 7, 10];
+
+/// A RIOT version, as far as it is relevant for compatibility decisions
+pub struct RiotVersion {
+    /// The version as given by RIOT (or a formatted version of the version code)
+    pub string: String,
+    pub year: u16,
+    pub month: u8,
+    pub patch: u16,
+    /// Whether this is a development version (or release candidate) before the release of the
+    /// year and month
+    pub prerelease: bool,
+}
+
+impl RiotVersion {
+    pub fn from_defines(defines: &HashMap<String, String>) -> Option<Self> {
+        if let Some(string) = super::riot_defines::string(defines, "RIOT_VERSION") {
+            if let Some(version) = Self::parse(&string) {
+                return Some(version);
+            }
+        }
+        Self::from_code(defines.get("RIOT_VERSION_CODE")?)
+    }
+
+    fn parse(string: &str) -> Option<Self> {
+        let (release, suffix) = string.split_once('-').unwrap_or((string, ""));
+        let mut parts = release.split('.');
+        let year = parts.next()?.parse().ok()?;
+        let month = parts.next()?.parse().ok()?;
+        let patch = match parts.next() {
+            Some(patch) => patch.parse().ok()?,
+            None => 0,
+        };
+        // Other suffixes (like the `-12-gabcdef` of a build after a release tag) are versions
+        // after the release
+        let prerelease = suffix.starts_with("devel") || suffix.to_lowercase().starts_with("rc");
+        Some(Self {
+            string: string.to_string(),
+            year,
+            month,
+            patch,
+            prerelease,
+        })
+    }
+
+    fn from_code(code: &str) -> Option<Self> {
+        let (year, month, patch) = match code
+            .strip_prefix("RIOT_VERSION_NUM(")
+            .and_then(|c| c.strip_suffix(')'))
+        {
+            Some(args) => {
+                let mut args = args.split(',').map(|a| a.trim().parse::<u64>());
+                (args.next()?.ok()?, args.next()?.ok()?, args.next()?.ok()?)
+            }
+            // As computed by RIOT_VERSION_NUM
+            None => {
+                let code: u64 = code.trim_end_matches(['U', 'L']).parse().ok()?;
+                (code >> 48, (code >> 32) & 0xffff, (code >> 16) & 0xffff)
+            }
+        };
+        Some(Self {
+            string: format!("{}.{:02}.{}", year, month, patch),
+            year: year.try_into().ok()?,
+            month: month.try_into().ok()?,
+            patch: patch.try_into().ok()?,
+            prerelease: false,
+        })
+    }
+
+    /// Whether this version has everything that was in the given release
+    fn is_at_least(&self, (year, month): (u16, u8)) -> bool {
+        match (self.year, self.month).cmp(&(year, month)) {
+            std::cmp::Ordering::Greater => true,
+            std::cmp::Ordering::Equal => !self.prerelease,
+            std::cmp::Ordering::Less => false,
+        }
+    }
+
+    /// Rust code defining the `RIOT_VERSION_...` constants
+    pub fn constants(&self) -> String {
+        format!(
+            "/// Year of the RIOT release this is built against (or which is being developed)\n\
+             pub const RIOT_VERSION_YEAR: u16 = {};\n\
+             /// Month of the RIOT release this is built against (or which is being developed)\n\
+             pub const RIOT_VERSION_MONTH: u8 = {};\n\
+             /// Point release number of the RIOT release this is built against\n\
+             pub const RIOT_VERSION_PATCH: u16 = {};\n\
+             /// Full version string of RIOT (as in the `RIOT_VERSION` define)\n\
+             pub const RIOT_VERSION_STR: &str = {:?};\n",
+            self.year, self.month, self.patch, self.string
+        )
+    }
+}
+
+/// All RIOT releases from the oldest supported one up to the end of the given year
+fn releases(until_year: u16) -> impl Iterator<Item = (u16, u8)> {
+    (OLDEST_RELEASE.0..=until_year)
+        .flat_map(|year| RELEASE_MONTHS.iter().map(move |month| (year, *month)))
+        .filter(|release| *release >= OLDEST_RELEASE)
+}
+
+fn cfg_name((year, month): (u16, u8)) -> String {
+    format!("riot_version_at_least_{}_{:02}", year, month)
+}
+
+/// The `riot_version_at_least_...` cfgs (and their check-cfg declarations) for the version, as
+/// lines for Cargo
+pub fn cfgs(version: Option<&RiotVersion>) -> Vec<String> {
+    let mut lines = vec![];
+    // Code may check for releases a bit into the future
+    let until_year = version.map(|v| v.year).unwrap_or(OLDEST_RELEASE.0) + 2;
+    for release in releases(until_year) {
+        lines.push(format!("rustc-check-cfg=cfg({})", cfg_name(release)));
+        if version.map(|v| v.is_at_least(release)).unwrap_or(false) {
+            lines.push(format!("rustc-cfg={}", cfg_name(release)));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        for (string, year, month, patch, prerelease) in [
+            ("2024.01-devel-123-gabcdef", 2024, 1, 0, true),
+            ("2024.10-devel", 2024, 10, 0, true),
+            ("2024.04-RC1", 2024, 4, 0, true),
+            ("2024.04", 2024, 4, 0, false),
+            ("2024.04.1", 2024, 4, 1, false),
+            ("2024.04-12-gabcdef", 2024, 4, 0, false),
+        ] {
+            let version = RiotVersion::parse(string).expect(string);
+            assert_eq!(version.string, string);
+            assert_eq!(version.year, year, "{}", string);
+            assert_eq!(version.month, month, "{}", string);
+            assert_eq!(version.patch, patch, "{}", string);
+            assert_eq!(version.prerelease, prerelease, "{}", string);
+        }
+        for string in ["", "2024", "devel", "2024.xx", "UNKNOWN (builtin)"] {
+            assert!(RiotVersion::parse(string).is_none(), "{}", string);
+        }
+    }
+
+    #[test]
+    fn from_defines() {
+        for (defines, string, year, month, patch) in [
+            (
+                &[("RIOT_VERSION", "\"2024.01-devel-123-gabcdef\"")][..],
+                "2024.01-devel-123-gabcdef",
+                2024,
+                1,
+                0,
+            ),
+            (
+                &[
+                    ("RIOT_VERSION", "\"UNKNOWN (builtin)\""),
+                    ("RIOT_VERSION_CODE", "RIOT_VERSION_NUM(2024, 4, 1, 0)"),
+                ],
+                "2024.04.1",
+                2024,
+                4,
+                1,
+            ),
+            (
+                &[("RIOT_VERSION_CODE", "569705370042302464ULL")],
+                "2024.04.1",
+                2024,
+                4,
+                1,
+            ),
+        ] {
+            let defines: HashMap<String, String> = defines
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect();
+            let version = RiotVersion::from_defines(&defines).expect(string);
+            assert_eq!(version.string, string);
+            assert_eq!(version.year, year, "{}", string);
+            assert_eq!(version.month, month, "{}", string);
+            assert_eq!(version.patch, patch, "{}", string);
+        }
+        assert!(RiotVersion::from_defines(&HashMap::new()).is_none());
+    }
+
+    #[test]
+    fn at_least() {
+        let development = RiotVersion::parse("2024.01-devel-123-gabcdef").unwrap();
+        assert!(development.is_at_least((2023, 10)));
+        assert!(!development.is_at_least((2024, 1)));
+        let release = RiotVersion::parse("2024.01").unwrap();
+        assert!(release.is_at_least((2024, 1)));
+        assert!(!release.is_at_least((2024, 4)));
+
+        let lines = cfgs(Some(&development));
+        assert!(lines.contains(&"rustc-cfg=riot_version_at_least_2023_10".to_string()));
+        assert!(!lines.contains(&"rustc-cfg=riot_version_at_least_2024_01".to_string()));
+        assert!(lines.contains(&"rustc-check-cfg=cfg(riot_version_at_least_2026_10)".to_string()));
+        assert!(cfgs(None).iter().all(|l| l.starts_with("rustc-check-cfg=")));
+    }
+}
@@ -0,0 +1,11 @@
+//! Rust identifiers for C names
+
+/// The identifier for a C name
+///
+/// Plain identifiers parse, keywords only as raw identifiers; where the name is shown (eg. in
+/// Debug output), it is to be taken from the identifier's `unraw()`.
+pub fn ident(name: &str) -> syn::Ident {
+    syn::parse_str(name)
+        .or_else(|_| syn::parse_str(&format!("r#{}", name)))
+        .unwrap_or_else(|_| panic!("C name {:?} is not usable as a Rust identifier", name))
+}
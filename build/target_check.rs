@@ -0,0 +1,235 @@
+//! Check that the Rust target fits the C target
+//!
+//! Bindings generated for one target and used on another have wrong sizes and offsets, which
+//! leads to memory corruption rather than build errors. Therefore, the target that clang is
+//! configured for in the CFLAGS (its `--target`, possibly modified by flags like `-m32` or
+//! `-mfloat-abi=hard`) is compared to Cargo's target configuration.
+//!
+//! Setting `RIOT_SYS_SKIP_TARGET_CHECK` skips the check, eg. for targets where the mapping below
+//! is incomplete.
+
+use std::env;
+
+/// Properties of a target that need to agree between C and Rust
+#[derive(Debug)]
+struct TargetProperties {
+    /// Rust's name for the architecture (as in `target_arch`)
+    arch: String,
+    pointer_width: u8,
+    big_endian: bool,
+    /// Whether floats are passed in floating point registers; only tracked on ARM
+    hard_float: Option<bool>,
+}
+
+/// Derive the properties from a clang target triple and the CFLAGS that modify it
+fn from_clang(triple: &str, cflags: &[String]) -> Option<TargetProperties> {
+    let flag = |f: &str| cflags.iter().any(|c| c == f);
+    let arch_part = triple.split('-').next()?;
+
+    let (arch, mut pointer_width) = match arch_part {
+        "x86_64" | "amd64" => ("x86_64", 64),
+        "i386" | "i486" | "i586" | "i686" | "x86" => ("x86", 32),
+        // Before the 32-bit arm, whose names it shares the prefix with
+        "aarch64" | "arm64" => ("aarch64", 64),
+        a if a.starts_with("arm") || a.starts_with("thumb") => ("arm", 32),
+        a if a.starts_with("riscv32") => ("riscv32", 32),
+        a if a.starts_with("riscv64") => ("riscv64", 64),
+        "xtensa" => ("xtensa", 32),
+        "mips" | "mipsel" => ("mips", 32),
+        "msp430" => ("msp430", 16),
+        "avr" => ("avr", 16),
+        _ => return None,
+    };
+    let mut arch = arch.to_string();
+    if arch == "x86_64" && flag("-m32") {
+        arch = "x86".to_string();
+        pointer_width = 32;
+    }
+
+    let big_endian = match arch_part {
+        // eg. armeb, armebv7r, thumbeb
+        a if a.ends_with("eb")
+            || a.starts_with("armeb")
+            || a.starts_with("thumbeb")
+            || a == "mips" =>
+        {
+            !flag("-mlittle-endian")
+        }
+        _ => flag("-mbig-endian"),
+    };
+
+    let hard_float = (arch == "arm").then(|| {
+        let float_abi = cflags
+            .iter()
+            .rev()
+            .find_map(|c| c.strip_prefix("-mfloat-abi="));
+        match float_abi {
+            Some(abi) => abi == "hard",
+            None => triple.ends_with("hf"),
+        }
+    });
+
+    Some(TargetProperties {
+        arch,
+        pointer_width,
+        big_endian,
+        hard_float,
+    })
+}
+
+/// Read the properties of the Rust target from the variables Cargo sets for build scripts
+fn from_cargo() -> Option<TargetProperties> {
+    let arch = env::var("CARGO_CFG_TARGET_ARCH").ok()?;
+    let pointer_width = env::var("CARGO_CFG_TARGET_POINTER_WIDTH")
+        .ok()?
+        .parse()
+        .ok()?;
+    let big_endian = env::var("CARGO_CFG_TARGET_ENDIAN").ok()? == "big";
+    let hard_float = (arch == "arm").then(|| {
+        env::var("CARGO_CFG_TARGET_ABI").map(|abi| abi.ends_with("hf")) == Ok(true)
+            || env::var("TARGET").map(|t| t.ends_with("hf")) == Ok(true)
+    });
+    Some(TargetProperties {
+        arch,
+        pointer_width,
+        big_endian,
+        hard_float,
+    })
+}
+
+/// The clang target given in the CFLAGS, or the host if there is none
+fn clang_triple(cflags: &[String]) -> Option<String> {
+    let mut args = cflags.iter();
+    let mut triple = None;
+    while let Some(arg) = args.next() {
+        if let Some(t) = arg.strip_prefix("--target=") {
+            triple = Some(t.to_string());
+        } else if arg == "-target" || arg == "--target" {
+            triple = args.next().cloned();
+        }
+    }
+    // Clang builds for the host when no target is given
+    triple.or_else(|| env::var("HOST").ok())
+}
+
+/// Panic with an explanation if the CFLAGS are for a different target than the Rust code
+pub fn check(cflags: &[String]) {
+    println!("cargo:rerun-if-env-changed=RIOT_SYS_SKIP_TARGET_CHECK");
+    if env::var("RIOT_SYS_SKIP_TARGET_CHECK").is_ok() {
+        return;
+    }
+
+    let triple = match clang_triple(cflags) {
+        Some(triple) => triple,
+        None => return,
+    };
+    let (c, rust) = match (from_clang(&triple, cflags), from_cargo()) {
+        (Some(c), Some(rust)) => (c, rust),
+        // Unknown architectures can not be checked
+        _ => return,
+    };
+
+    let mut mismatches = vec![];
+    if c.arch != rust.arch {
+        mismatches.push(format!(
+            "architecture is {} in C but {} in Rust",
+            c.arch, rust.arch
+        ));
+    }
+    if c.pointer_width != rust.pointer_width {
+        mismatches.push(format!(
+            "pointers are {} bit wide in C but {} bit in Rust",
+            c.pointer_width, rust.pointer_width
+        ));
+    }
+    if c.big_endian != rust.big_endian {
+        let endian = |big| if big { "big" } else { "little" };
+        mismatches.push(format!(
+            "byte order is {} endian in C but {} endian in Rust",
+            endian(c.big_endian),
+            endian(rust.big_endian)
+        ));
+    }
+    if let (Some(c_hf), Some(rust_hf)) = (c.hard_float, rust.hard_float) {
+        if c_hf != rust_hf {
+            let abi = |hf| if hf { "hard-float" } else { "soft-float" };
+            mismatches.push(format!(
+                "float ABI is {} in C but {} in Rust",
+                abi(c_hf),
+                abi(rust_hf)
+            ));
+        }
+    }
+
+    if !mismatches.is_empty() {
+        panic!(
+            "The Rust target {} does not fit the C target {} of the RIOT CFLAGS: {}.\n\
+             Bindings built this way would not match the C code's memory layout and calling \
+             conventions. Make sure the Rust target is the one RIOT selects for the board (eg. by \
+             building through RIOT's build system), or set RIOT_SYS_SKIP_TARGET_CHECK if this \
+             check is wrong for your target.",
+            env::var("TARGET").unwrap_or_default(),
+            triple,
+            mismatches.join("; ")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clang_targets() {
+        for (triple, cflags, arch, pointer_width, big_endian, hard_float) in [
+            ("arm64-apple-darwin", &[][..], "aarch64", 64, false, None),
+            ("aarch64-unknown-linux-gnu", &[], "aarch64", 64, false, None),
+            ("thumbv7em-none-eabi", &[], "arm", 32, false, Some(false)),
+            ("thumbv7em-none-eabihf", &[], "arm", 32, false, Some(true)),
+            (
+                "thumbv7em-none-eabi",
+                &["-mfloat-abi=hard"],
+                "arm",
+                32,
+                false,
+                Some(true),
+            ),
+            ("armebv7r-none-eabi", &[], "arm", 32, true, Some(false)),
+            ("riscv32-unknown-elf", &[], "riscv32", 32, false, None),
+            (
+                "riscv32imac-unknown-none-elf",
+                &[],
+                "riscv32",
+                32,
+                false,
+                None,
+            ),
+            ("x86_64-pc-linux-gnu", &["-m32"], "x86", 32, false, None),
+            ("x86_64-pc-linux-gnu", &[], "x86_64", 64, false, None),
+            ("msp430-elf", &[], "msp430", 16, false, None),
+        ] {
+            let cflags: Vec<String> = cflags.iter().map(|c| c.to_string()).collect();
+            let properties = from_clang(triple, &cflags).expect(triple);
+            assert_eq!(properties.arch, arch, "{}", triple);
+            assert_eq!(properties.pointer_width, pointer_width, "{}", triple);
+            assert_eq!(properties.big_endian, big_endian, "{}", triple);
+            assert_eq!(properties.hard_float, hard_float, "{}", triple);
+        }
+        assert!(from_clang("sparc-unknown-elf", &[]).is_none());
+    }
+
+    #[test]
+    fn triple_from_cflags() {
+        for (cflags, triple) in [
+            (&["--target=thumbv7em-none-eabi"][..], "thumbv7em-none-eabi"),
+            (
+                &["-target", "riscv32-unknown-elf", "-O2"],
+                "riscv32-unknown-elf",
+            ),
+            (&["--target", "arm64-apple-darwin"], "arm64-apple-darwin"),
+        ] {
+            let cflags: Vec<String> = cflags.iter().map(|c| c.to_string()).collect();
+            assert_eq!(clang_triple(&cflags).as_deref(), Some(triple));
+        }
+    }
+}
@@ -1,3 +1,8 @@
+// Differs between applications that are otherwise configured identically, and
+// would thus keep their C2Rust output from being shared through the cache (see
+// build/c2rust_cache.rs). Bindgen still provides it.
+#undef RIOT_APPLICATION
+
 // Workaround for https://github.com/immunant/c2rust/issues/345
 //
 // As these are not really in the call tree of any public RIOT function, making
@@ -26,6 +31,11 @@ static inline void __masked_builtin_arm_set_fpscr(int fpscr){
 	missing_implementation_for_fpscr_in_c2rust_see_issue_345 = fpscr;
 }
 
+// When C2Rust was found to handle atomics, and src/intrinsics_replacements.rs
+// provides all the intrinsics it translates them into for the target (see
+// build/c2rust_probe.rs), none of this is needed, and build.rs defines
+// RIOT_SYS_C2RUST_ATOMICS.
+#ifndef RIOT_SYS_C2RUST_ATOMICS
 // This is currently the only relevant user of stdatomic.h. As it doesn't
 // access its relevant atomic field from static inlines (and thus from built
 // Rust) and forbids users from touching it themselves, we can work around
@@ -142,6 +152,7 @@ static inline void __masked_builtin_arm_set_fpscr(int fpscr){
 #undef atomic_ptrdiff_t
 #undef atomic_intmax_t
 #undef atomic_uintmax_t
+#endif /* RIOT_SYS_C2RUST_ATOMICS */
 
 // Allow header files that pull in lots of odd stuff but don't depend on
 // inlines -- like nimble's host/ble_gap.h -- to opt out of C2Rust altogether
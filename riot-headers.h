@@ -201,8 +201,10 @@
 
 /* packages */
 #ifdef MODULE_NIMBLE_AUTOADV
-#  ifndef IS_C2RUST
-/* These contain variadic functions ... and besides, let's only use C2Rust where we need it */
+/* These contain variadic functions, which only transpile into usable code with
+ * the c-variadic feature ... and besides, let's only use C2Rust where we need
+ * it */
+#  if !defined(IS_C2RUST) || defined(RIOT_SYS_C_VARIADIC)
 #    include "nimble_autoadv.h"
 #    include "nimble_autoadv_params.h"
 #  endif
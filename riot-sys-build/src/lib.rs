@@ -0,0 +1,116 @@
+//! # Compiling C code against riot-sys' RIOT configuration
+//!
+//! Crates that wrap RIOT sometimes need small pieces of C code of their own (eg. shims around
+//! macros that bindgen can not translate). Those need to be built with exactly the compiler,
+//! include paths, defines and ABI flags that riot-sys used, which riot-sys exports to crates
+//! depending on it as `DEP_RIOT_SYS_...` variables.
+//!
+//! This crate reads them in a build script. To use it, depend on `riot-sys` (so that Cargo passes
+//! on its variables) and add `riot-sys-build` as a build dependency:
+//!
+//! ```no_run
+//! // in build.rs
+//! riot_sys_build::RiotConfig::from_env()
+//!     .cc_build()
+//!     .file("src/shims.c")
+//!     .compile("shims");
+//! ```
+//!
+//! Where the C code is not built with [cc](https://docs.rs/cc), [RiotConfig::clang_args] gives the arguments for
+//! invoking the compiler.
+
+use std::env;
+
+/// The parts of RIOT's CFLAGS that are relevant for building C code against RIOT
+#[derive(Debug, Clone)]
+pub struct RiotConfig {
+    /// The (clang) C compiler
+    pub cc: String,
+    pub includes: Vec<String>,
+    pub system_includes: Vec<String>,
+    /// Files that are included before anything else (typically riotbuild.h)
+    pub include_files: Vec<String>,
+    /// Defines as `NAME` or `NAME=VALUE`
+    pub defines: Vec<String>,
+    /// Flags that influence how code is built, such as the target, `-m` and `-f` options
+    pub abi_cflags: Vec<String>,
+}
+
+/// Read a variable exported by riot-sys
+fn dep_var(name: &str) -> String {
+    let var = format!("DEP_RIOT_SYS_{}", name);
+    println!("cargo:rerun-if-env-changed={}", var);
+    env::var(&var).unwrap_or_else(|_| {
+        panic!(
+            "{} is not set. The crate needs to depend on riot-sys directly, in a version that \
+             exports the CFLAGS in parts.",
+            var
+        )
+    })
+}
+
+/// Read a shell quoted list exported by riot-sys
+fn dep_list(name: &str) -> Vec<String> {
+    shlex::split(&dep_var(name))
+        .unwrap_or_else(|| panic!("Odd shell escaping in DEP_RIOT_SYS_{}", name))
+}
+
+impl RiotConfig {
+    /// Read the configuration from the variables riot-sys exports
+    ///
+    /// This is to be called from a build script of a crate that depends on riot-sys.
+    pub fn from_env() -> Self {
+        Self {
+            cc: dep_var("CC"),
+            includes: dep_list("INCLUDES"),
+            system_includes: dep_list("SYSTEM_INCLUDES"),
+            include_files: dep_list("INCLUDE_FILES"),
+            defines: dep_list("DEFINES"),
+            abi_cflags: dep_list("ABI_CFLAGS"),
+        }
+    }
+
+    /// Arguments to the C compiler (without the compiler itself)
+    pub fn clang_args(&self) -> Vec<String> {
+        let mut args = self.abi_cflags.clone();
+        args.extend(self.defines.iter().map(|d| format!("-D{}", d)));
+        args.extend(self.includes.iter().map(|i| format!("-I{}", i)));
+        for include in self.system_includes.iter() {
+            args.push("-isystem".to_string());
+            args.push(include.clone());
+        }
+        for file in self.include_files.iter() {
+            args.push("-include".to_string());
+            args.push(file.clone());
+        }
+        args
+    }
+
+    /// A [cc::Build] set up with RIOT's compiler and flags
+    ///
+    /// cc's own default flags (target, optimization, position independence) are disabled, as
+    /// RIOT's flags already determine the target and ABI. Optimization and debug flags can be set
+    /// on the result as needed.
+    #[cfg(feature = "cc")]
+    pub fn cc_build(&self) -> cc::Build {
+        let mut build = cc::Build::new();
+        build.compiler(&self.cc).no_default_flags(true);
+        for flag in self.abi_cflags.iter() {
+            build.flag(flag);
+        }
+        for define in self.defines.iter() {
+            match define.split_once('=') {
+                Some((name, value)) => build.define(name, value),
+                None => build.define(define, None),
+            };
+        }
+        build.includes(self.includes.iter());
+        for include in self.system_includes.iter() {
+            build.flag("-isystem").flag(include);
+        }
+        for file in self.include_files.iter() {
+            build.flag("-include").flag(file);
+        }
+        build
+    }
+}
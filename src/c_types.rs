@@ -0,0 +1,13 @@
+//! C's size types, as they are on the target
+//!
+//! Whether `size_t`, `ssize_t` and `ptrdiff_t` have the size of a pointer (and thus are `usize`
+//! and `isize`), and how large `off_t` is, depends on the target and its libc. These aliases are
+//! set up at build time from what the C compiler reports for the configured target.
+//!
+//! Where `size_t`, `ssize_t` and `ptrdiff_t` all have pointer size (which is the case on most
+//! targets), the bindings use `usize` and `isize` for them directly, so lengths can be passed
+//! without casts. (This changed the types of the bindings; see the changelog).
+//!
+//! The module is empty if the sizes could not be determined (which shows as a build warning).
+
+include!(concat!(env!("OUT_DIR"), "/c_types.rs"));
@@ -0,0 +1,17 @@
+//! Rust enums for RIOT's C enums
+//!
+//! Bindgen represents every C enum as an integer type with one constant per enumerator in the
+//! root module (eg. [`thread_status_t`](crate::thread_status_t) and
+//! [`thread_status_t_STATUS_RUNNING`](crate::thread_status_t_STATUS_RUNNING)); those are what
+//! is passed to and from C functions.
+//!
+//! For each such enum, this module contains a Rust enum of the same name with one variant per
+//! enumerator, named like the enumerator in C. Raw values are converted into them using
+//! `TryFrom` (which fails for values that were not known when riot-sys was built), and back using
+//! `From`. Their Display shows the C name of the enumerator.
+//!
+//! The enums are `#[non_exhaustive]`, as the available enumerators depend on the RIOT version and
+//! configuration.
+#![allow(rustdoc::broken_intra_doc_links)]
+
+include!(concat!(env!("OUT_DIR"), "/enums.rs"));
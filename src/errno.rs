@@ -0,0 +1,57 @@
+//! Error numbers
+//!
+//! Most RIOT functions report errors by returning a negative error number (eg. `-ENOENT`). The
+//! [Errno] type represents such a number (as a positive value), with constants for all error
+//! numbers that the target's libc defines (eg. [`Errno::ENOENT`]), independent of which of them
+//! are visible through bindgen.
+//!
+//! If the `tiny_strerror` module is in use, errors are displayed with their description;
+//! otherwise, with their name.
+#![allow(rustdoc::broken_intra_doc_links)]
+
+use core::ffi::c_int;
+
+/// A (positive) error number
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Errno(pub c_int);
+
+include!(concat!(env!("OUT_DIR"), "/errno.rs"));
+
+impl Errno {
+    /// Decode the error from a negative return value, or `None` if the value is not negative (or
+    /// has no positive counterpart, as `c_int::MIN`)
+    pub const fn from_negative(ret: c_int) -> Option<Self> {
+        if ret >= 0 {
+            return None;
+        }
+        match ret.checked_neg() {
+            Some(errno) => Some(Self(errno)),
+            None => None,
+        }
+    }
+
+    /// The negative value, as returned by RIOT functions
+    pub const fn to_negative(self) -> c_int {
+        -self.0
+    }
+}
+
+/// Turn the usual return value of RIOT functions into a Result
+///
+/// Non-negative values are passed on as they are, negative values are decoded into an [Errno]
+/// (except for `c_int::MIN`, which is passed on as well, see [Errno::from_negative]).
+pub const fn check(ret: c_int) -> Result<c_int, Errno> {
+    match Errno::from_negative(ret) {
+        Some(errno) => Err(errno),
+        None => Ok(ret),
+    }
+}
+
+impl core::fmt::Debug for Errno {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "Errno({})", self.0),
+        }
+    }
+}
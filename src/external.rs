@@ -0,0 +1,20 @@
+//! Bindings for modules from RIOT's `EXTERNAL_MODULE_DIRS`
+//!
+//! Every external module that is in use (ie. whose include directory is in the CFLAGS) gets a
+//! module in here, named after the module's directory (with any characters that can't be in a
+//! Rust identifier replaced by underscores; directories whose names still don't make an
+//! identifier, eg. because they start with a digit or are a keyword, are rejected by the build).
+//! It contains what bindgen produces for all the headers in the module's include directory, along
+//! with their static inline functions from the [inline] module (or their shims, see the
+//! `static-inline-shims` feature).
+//!
+//! Types from RIOT that these refer to are the ones of the root module. Where the root module
+//! does not have them (eg. because the application restricted its items with
+//! `RIOT_SYS_BINDGEN_ALLOWLIST`), they are generated in the external module.
+//!
+//! [inline]: crate::inline
+#![allow(rustdoc::bare_urls)]
+#![allow(rustdoc::invalid_rust_codeblocks)]
+#![allow(rustdoc::broken_intra_doc_links)]
+
+include!(concat!(env!("OUT_DIR"), "/external.rs"));
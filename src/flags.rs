@@ -0,0 +1,13 @@
+//! Typed sets of RIOT flags
+//!
+//! RIOT's flags are defines or enumerators that share a common prefix and are or-ed together, eg.
+//! [`THREAD_CREATE_SLEEPING`](crate::THREAD_CREATE_SLEEPING). For the families of flags listed in
+//! riot-sys's build.rs, this module contains a newtype around the raw integer, with the flags as
+//! associated constants named without the prefix (eg. `ThreadCreate::SLEEPING`).
+//!
+//! The flag sets support `|` and `&`, [`contains`](ThreadCreate::contains) checks, and
+//! conversion from and to the raw value; their Debug output lists the flags that are set. Types
+//! are only present if any of their flags are known in the current configuration.
+#![allow(rustdoc::broken_intra_doc_links)]
+
+include!(concat!(env!("OUT_DIR"), "/flags.rs"));
@@ -8,6 +8,8 @@
 //! Use these functions through the re-export in the main module, for the C headers may flip-flop
 //! between static inline and linked.
 //!
+//! When riot-sys is built without C2Rust (see the `no-inline` feature), this module is empty.
+//!
 //! ---
 //!
 //! Some special treatment has been applied in the course of the transpilation process:
@@ -20,6 +22,16 @@
 //!   transpiler to recognize which type this is actually for. That information is tracked manually
 //!   in `build.rs` as a list of known initializers. They get turned into const functions in the
 //!   style of `fn init_MUTEX_INIT() -> mutex_t`.
+//! * Functions that take a `va_list` argument get that argument's type replaced with
+//!   [`core::convert::Infallible`], making them impossible to call, unless the `c-variadic` feature
+//!   is enabled.
+//! * For some common uses of variadic functions (eg. `printf` or `LOG` with a single argument),
+//!   wrappers with fixed arguments are provided in the style of `fn variadic_printf_str(format:
+//!   *const c_char, arg: *const c_char) -> c_int`. Output from shell commands goes through
+//!   these as well, as the shell has no printing API of its own. There are no wrappers for
+//!   nimble_autoadv: its own functions take fixed arguments and are bound by bindgen, and its
+//!   header is only transpiled with the `c-variadic` feature because of the variadic functions
+//!   it pulls in from NimBLE.
 //!
 // While it'd be tempting to clean them all up in RIOT by a large constification haul, now is not
 // the time for that
@@ -32,6 +32,20 @@
 //! The flags will be interpreted by libclang based tools; care must be taken to pass in flags
 //! suitable for clang and not for GCC.
 //!
+//! The Rust target needs to match the C target (for example, `thumbv7em-none-eabihf` for a
+//! Cortex-M4 with `-mfloat-abi=hard`). The build fails with an explanation if the architecture,
+//! pointer width, byte order or ARM float ABI of the CFLAGS' clang target differ from those of the
+//! Rust target; setting `RIOT_SYS_SKIP_TARGET_CHECK` disables that check.
+//!
+//! Builds without libclang, C2Rust or RIOT (eg. for documentation, or CI jobs that only check
+//! code) can use bindings that were generated earlier: A regular build with
+//! `RIOT_SYS_SAVE_PREGENERATED_DIR` set stores all generated files and the information passed to
+//! Cargo in that directory, and a build with `RIOT_SYS_PREGENERATED_DIR` set takes them from
+//! there without generating anything. If CFLAGS are available in such a build, they (along with
+//! the enabled features) need to match the stored configuration. On docs.rs, a `pregenerated`
+//! directory in the crate is used automatically if present. Shims for static inline functions
+//! are not stored, so their functions can not be linked in such builds.
+//!
 //! These steps are automated in RIOT's build system.
 //!
 //!
@@ -41,6 +55,57 @@
 //! as downstream crates require that information (typically to allow a crate to work across a
 //! wider range of RIOT versions); see the section below for details.
 //!
+//! Transpiling with C2Rust takes a large share of the build time; its results are cached across
+//! builds with identical configurations (even across different applications). The cache is kept
+//! in a `riot-sys-cache` directory inside Cargo's target directory, or in `RIOT_SYS_CACHE_DIR` if
+//! that is set; setting it to an empty value disables caching.
+//!
+//! If C2Rust is not installed (or the `no-inline` feature is enabled), riot-sys still builds, but
+//! with a warning: Static inline functions are then compiled with `RIOT_CC` into shims that are
+//! linked, and the [inline] module stays empty. Those functions, as well as the `macro_` and
+//! `variadic_` functions, are still available in the root module, but they can not be inlined
+//! into Rust code, and none of them are const.
+//!
+//! With the `static-inline-shims` feature, those shims are built in addition to the C2Rust
+//! translation. This makes static inline functions available that C2Rust can not translate (or
+//! that are left out from the translation, eg. because they use atomics). For functions that are
+//! available both ways, the inlinable version is used in the root module unless the function is
+//! listed as shim-only in build.rs.
+//!
+//! The bindgen output can be restricted to the items an application actually uses, which reduces
+//! compile times considerably: `RIOT_SYS_BINDGEN_ALLOWLIST` and `RIOT_SYS_BINDGEN_BLOCKLIST` take
+//! whitespace separated item patterns (eg. `ztimer_.* gcoap_.*`), `RIOT_SYS_BINDGEN_CONFIG` can
+//! point to a file with `allow <pattern>` and `block <pattern>` lines, and
+//! `RIOT_SYS_BINDGEN_USED_ITEMS` can point to a file listing one used item name per line (as
+//! produced by the consuming crate). Note that markers (see below) that depend on the bindgen
+//! output are only set if the respective items are still produced.
+//!
+//! With the `derive-eq-hash` feature, bindgen types additionally implement `PartialEq`, `Eq` and
+//! `Hash` wherever all their fields allow that (eg. to compare endpoints or use addresses as map
+//! keys). With the `zerocopy` feature, some plain-old-data types (addresses, endpoints, `phydat_t`
+//! and `uuid_t`) implement [zerocopy](https://docs.rs/zerocopy)'s `FromBytes` (and where they have
+//! no padding `IntoBytes`), so that they can be read from buffers without unsafe code.
+//!
+//! The board, CPU, MCU and CPU architecture that RIOT is built for are taken from the defines
+//! `RIOT_BOARD`, `RIOT_CPU`, `RIOT_MCU` and `CPU_ARCH_...` (in the CFLAGS or in riotbuild.h), and
+//! exported as `DEP_RIOT_SYS_BOARD`, `DEP_RIOT_SYS_CPU`, `DEP_RIOT_SYS_MCU` and
+//! `DEP_RIOT_SYS_ARCH` to crates that set `links = "riot-sys"`. Inside riot-sys, they are also set
+//! as cfgs (eg. `#[cfg(riot_board = "native")]`, or `riot_arch = "armv7m"`); as cfgs do not
+//! propagate to other crates, those need to set them in their own build.rs from the variables.
+//!
+//! RIOT's features (what the board and CPU provide, eg. `periph_gpio_irq`, and which of them are
+//! used) are taken from `RIOT_FEATURES_PROVIDED` and `RIOT_FEATURES_USED` (or from the RIOT build
+//! under riot-rs). They are set as cfgs (eg. `#[cfg(riot_feature = "periph_gpio_irq")]`, with
+//! used ones also as `riot_feature_used`), and exported as space separated lists in
+//! `DEP_RIOT_SYS_FEATURES` (all of them) and `DEP_RIOT_SYS_FEATURES_USED`.
+//!
+//! The RIOT version (from the `RIOT_VERSION` define, eg. `2024.10-devel-123-gabcdef`) is exported
+//! as `DEP_RIOT_SYS_RIOT_VERSION`, and available as [RIOT_VERSION_YEAR], [RIOT_VERSION_MONTH],
+//! [RIOT_VERSION_PATCH] and [RIOT_VERSION_STR]. For every release since 2022.01 that the version
+//! contains, a cfg like `riot_version_at_least_2024_10` is set; development versions and release
+//! candidates do not count as containing the release they lead up to. Like markers (see below),
+//! this is only suitable for coarse decisions.
+//!
 //!
 //! ## Extension
 //!
@@ -48,6 +113,19 @@
 //! header files are included in this crate's `riot-headers.h` header file. If you
 //! need access to more RIOT APIs, more includes can be added there.
 //!
+//! Applications can add headers of their own (eg. of out-of-tree drivers or of their own C
+//! modules) without patching this crate: `RIOT_SYS_EXTRA_HEADERS` takes a whitespace separated
+//! list of headers (found through the include path, or given as absolute paths) that are
+//! processed by both bindgen and C2Rust. As C2Rust only translates static inline functions that
+//! are used, `RIOT_SYS_EXTRA_USE` can contain C statements that use them (eg.
+//! `my_driver_get(0); my_driver_is_ready(0);`). Items from these headers are available like the
+//! ones from RIOT, with the static inline functions in the [inline] module. The list of extra
+//! headers is made available to dependent crates as `DEP_RIOT_SYS_EXTRA_HEADERS`.
+//!
+//! Modules from RIOT's `EXTERNAL_MODULE_DIRS` are picked up automatically: The headers in their
+//! include directories are processed the same way, but their items are placed in the [external]
+//! module (eg. as `riot_sys::external::my_module::my_function`).
+//!
 //!
 //! ## Versioning
 //!
@@ -63,6 +141,21 @@
 //! (to determine whether a symbol is imported in the first place, eg. when RIOT renames something)
 //! and `DEP_RIOT_SYS_CFLAGS` which includes the enabled modules.
 //!
+//! For compiling C code of their own, crates get the CFLAGS also in parts: the include
+//! directories in `DEP_RIOT_SYS_INCLUDES` and `DEP_RIOT_SYS_SYSTEM_INCLUDES`, the always included
+//! files (riotbuild.h) in `DEP_RIOT_SYS_INCLUDE_FILES`, the defines in `DEP_RIOT_SYS_DEFINES`, and
+//! the flags that affect the target and ABI in `DEP_RIOT_SYS_ABI_CFLAGS`, each as a shell quoted
+//! list. The `riot-sys-build` crate in this repository reads them in a build script, and provides
+//! a [cc](https://docs.rs/cc) build or compiler arguments set up accordingly.
+//!
+//! To see how the API changes between RIOT versions or configurations, `RIOT_SYS_API_BASELINE`
+//! can point to an earlier set of generated bindings (a directory as stored with
+//! `RIOT_SYS_SAVE_PREGENERATED_DIR` or by the `riot-sys-snapshot` tool, or a `bindings.rs` file).
+//! The build then warns about added, removed and changed items, and about functions that moved
+//! between being linked and being static inline; the full report is written to `api_diff.txt`
+//! next to the bindings. The `riot-sys-api-diff` tool in `tools/` produces the same report for
+//! any two sets of bindings.
+//!
 //! ### Markers
 //!
 //! **Deprecated, see below**.
@@ -116,11 +209,15 @@
 //! All bindgen types are reexported in the main module and exclusively public through there. The
 //! C2Rust types largely reside in the [inline] module, with some pub used into the root module as
 //! necessary or convenient.
+//! C enums, which bindgen represents as integers and constants, additionally get Rust enums in
+//! the [enums] module. Families of flags (eg. `THREAD_CREATE_*`) get typed flag sets in the
+//! [flags] module.
 #![no_std]
 #![allow(non_upper_case_globals)]
 #![allow(non_camel_case_types)]
 #![allow(non_snake_case)]
 #![cfg_attr(feature = "keep-extern-types", feature(extern_types))]
+#![cfg_attr(feature = "c-variadic", feature(c_variadic))]
 
 #[deprecated(note = "Use core::ffi types directly")]
 pub mod libc;
@@ -128,9 +225,15 @@ pub mod libc;
 mod intrinsics_replacements;
 
 mod bindgen;
+pub mod c_types;
+pub mod enums;
+pub mod errno;
+pub mod external;
+pub mod flags;
 pub mod inline;
 
 include!(concat!(env!("OUT_DIR"), "/toplevel_from_inline.rs"));
+include!(concat!(env!("OUT_DIR"), "/riot_version.rs"));
 pub use bindgen::*;
 
 // re-export RIOT-rs core (used by riot-wrappers)
@@ -19,7 +19,8 @@ pub use core::ffi::{
     c_ulong,
     c_ulonglong,
     c_ushort,
-    // Not even loading size_t and ssize_t as they don't fit with bindgen's mapping anyway
+    // Not even loading size_t and ssize_t as they don't fit with bindgen's mapping anyway; see
+    // crate::c_types for the target's versions
 };
 
 pub use core::ffi::c_void;
@@ -0,0 +1,39 @@
+//! Compare the API of two sets of riot-sys' generated bindings
+//!
+//! Usage: `riot-sys-api-diff <old> <new>`
+//!
+//! Both sides can be a directory with generated files (a snapshot from riot-sys-snapshot,
+//! pregenerated bindings, or riot-sys' OUT_DIR), or a single `bindings.rs` file. The report lists
+//! added, removed and changed items, and functions that moved between being linked and being
+//! static inline. The exit status is 1 if there are any differences.
+//!
+//! The same comparison is done in a riot-sys build when `RIOT_SYS_API_BASELINE` is set.
+
+#[path = "../../../build/api_diff.rs"]
+mod api_diff;
+
+use std::path::Path;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let (old, new) = match &args[..] {
+        [_, old, new] => (Path::new(old), Path::new(new)),
+        _ => {
+            eprintln!("Usage: {} <old> <new>", args[0]);
+            std::process::exit(2);
+        }
+    };
+
+    let load = |path| {
+        api_diff::Api::load(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        })
+    };
+    let report = api_diff::compare(&load(old), &load(new));
+    print!("{}", report);
+    if !report.is_empty() {
+        eprintln!("{}", report.summary());
+        std::process::exit(1);
+    }
+}
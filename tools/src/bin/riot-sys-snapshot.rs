@@ -0,0 +1,192 @@
+//! Produce snapshots of riot-sys' generated bindings for a set of configurations
+//!
+//! Usage: `riot-sys-snapshot <fixtures> <output>`
+//!
+//! Every directory in `<fixtures>` describes one configuration (eg. one board with a set of
+//! modules) through files that are recorded from a RIOT build:
+//!
+//! * `target`: The Rust target to build for (eg. `thumbv7em-none-eabihf`)
+//! * Either `compile_commands.json` (optionally along with `usemodule`, the value of
+//!   RIOT_USEMODULE), or `cc` and `cflags` (the values of RIOT_CC and RIOT_CFLAGS)
+//! * `features` (optional): riot-sys features to enable, separated by whitespace
+//!
+//! For each configuration, riot-sys is built with Cargo, so the generation runs exactly as in any
+//! other build; the generated files are picked up through `RIOT_SYS_SAVE_PREGENERATED_DIR`. They
+//! are written to `<output>/<configuration>/`, along with a `cargo.txt` listing what is passed on
+//! to Cargo (cfgs, markers and `DEP_RIOT_SYS_...` variables).
+//!
+//! The snapshots are normalized to not depend on where things are located: Paths to riot-sys, to
+//! the fixture, to the build directory and to `RIOTBASE` (if set) are replaced with placeholders,
+//! so that snapshots can be committed and diffed between RIOT or riot-sys versions.
+//!
+//! The Rust targets need to be installed, and the tools riot-sys needs (libclang, C2Rust) need to
+//! be available, just as for any riot-sys build.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Location of riot-sys
+fn riot_sys_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("Tools are inside the riot-sys directory")
+        .to_path_buf()
+}
+
+fn read_optional(path: &Path) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_string())
+}
+
+/// Paths that are replaced in the snapshots, with their placeholders
+fn replacements(fixture: &Path, build_dir: &Path) -> Vec<(String, &'static str)> {
+    let mut replacements = vec![
+        (build_dir.display().to_string(), "$BUILD"),
+        (fixture.display().to_string(), "$FIXTURE"),
+        (riot_sys_dir().display().to_string(), "$RIOT_SYS"),
+    ];
+    if let Ok(riotbase) = std::env::var("RIOTBASE") {
+        replacements.push((riotbase.trim_end_matches('/').to_string(), "$RIOTBASE"));
+    }
+    // Longest first, in case they are nested
+    replacements.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+    replacements
+}
+
+fn normalize(text: &str, replacements: &[(String, &str)]) -> String {
+    let mut text = text.to_string();
+    for (path, placeholder) in replacements {
+        text = text.replace(path.as_str(), placeholder);
+    }
+    text
+}
+
+/// Build riot-sys in the given configuration and write its snapshot
+fn snapshot(fixture: &Path, build_dir: &Path, output: &Path) -> Result<(), String> {
+    let target = read_optional(&fixture.join("target"))
+        .ok_or_else(|| "Fixture has no target file".to_string())?;
+    let features = read_optional(&fixture.join("features")).unwrap_or_default();
+
+    // A new location every time, as that makes Cargo rerun the build script even if nothing else
+    // changed since the last run
+    let saved = build_dir.join(format!(
+        "saved-{}-{}",
+        std::process::id(),
+        fixture
+            .file_name()
+            .expect("Fixture is a named directory")
+            .to_string_lossy()
+    ));
+
+    let mut cargo = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()));
+    cargo
+        .arg("check")
+        .arg("--manifest-path")
+        .arg(riot_sys_dir().join("Cargo.toml"))
+        .arg("--target")
+        .arg(&target)
+        .arg("--target-dir")
+        .arg(build_dir.join("target"))
+        .arg("--features")
+        .arg(&features)
+        .env("RIOT_SYS_SAVE_PREGENERATED_DIR", &saved)
+        .env_remove("RIOT_SYS_PREGENERATED_DIR");
+    let compile_commands = fixture.join("compile_commands.json");
+    if compile_commands.exists() {
+        cargo
+            .env("RIOT_COMPILE_COMMANDS_JSON", &compile_commands)
+            .env(
+                "RIOT_USEMODULE",
+                read_optional(&fixture.join("usemodule")).unwrap_or_default(),
+            )
+            .env_remove("RIOT_CC")
+            .env_remove("RIOT_CFLAGS");
+    } else {
+        let cc = read_optional(&fixture.join("cc"))
+            .ok_or_else(|| "Fixture has neither compile_commands.json nor cc".to_string())?;
+        let cflags = read_optional(&fixture.join("cflags"))
+            .ok_or_else(|| "Fixture has no cflags".to_string())?;
+        cargo
+            .env("RIOT_CC", cc)
+            .env("RIOT_CFLAGS", cflags)
+            .env_remove("RIOT_COMPILE_COMMANDS_JSON");
+    }
+    let status = cargo
+        .status()
+        .map_err(|e| format!("Failed to run cargo: {}", e))?;
+    if !status.success() {
+        return Err(format!("Building riot-sys failed ({})", status));
+    }
+
+    let manifest = std::fs::read_to_string(saved.join("manifest.json"))
+        .map_err(|e| format!("Build did not produce a manifest: {}", e))?;
+    let manifest: serde_json::Value =
+        serde_json::from_str(&manifest).map_err(|e| format!("Invalid manifest: {}", e))?;
+
+    let replacements = replacements(fixture, build_dir);
+    if output.exists() {
+        std::fs::remove_dir_all(output).map_err(|e| format!("Failed to clean up: {}", e))?;
+    }
+    std::fs::create_dir_all(output).map_err(|e| format!("Failed to create output: {}", e))?;
+
+    let mut cargo_lines = String::new();
+    for line in manifest["cargo"].as_array().into_iter().flatten() {
+        cargo_lines.push_str(line.as_str().unwrap_or_default());
+        cargo_lines.push('\n');
+    }
+    std::fs::write(
+        output.join("cargo.txt"),
+        normalize(&cargo_lines, &replacements),
+    )
+    .map_err(|e| format!("Failed to write cargo.txt: {}", e))?;
+
+    for file in manifest["files"].as_array().into_iter().flatten() {
+        let file = file.as_str().unwrap_or_default();
+        let content = std::fs::read_to_string(saved.join(file))
+            .map_err(|e| format!("Failed to read {}: {}", file, e))?;
+        std::fs::write(output.join(file), normalize(&content, &replacements))
+            .map_err(|e| format!("Failed to write {}: {}", file, e))?;
+    }
+
+    std::fs::remove_dir_all(&saved).map_err(|e| format!("Failed to clean up: {}", e))?;
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let (fixtures, output) = match &args[..] {
+        [_, fixtures, output] => (Path::new(fixtures), Path::new(output)),
+        _ => {
+            eprintln!("Usage: {} <fixtures> <output>", args[0]);
+            std::process::exit(1);
+        }
+    };
+
+    let mut names: Vec<String> = std::fs::read_dir(fixtures)
+        .expect("Failed to list fixtures")
+        .map(|entry| entry.expect("Failed to list fixtures"))
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+
+    let build_dir = std::env::temp_dir().join("riot-sys-snapshot");
+    let mut failed = vec![];
+    for name in names {
+        eprintln!("Generating snapshot for {}", name);
+        let fixture = fixtures
+            .join(&name)
+            .canonicalize()
+            .expect("Fixture was just listed");
+        if let Err(e) = snapshot(&fixture, &build_dir, &output.join(&name)) {
+            eprintln!("Snapshot for {} failed: {}", name, e);
+            failed.push(name);
+        }
+    }
+
+    if !failed.is_empty() {
+        eprintln!("Failed configurations: {}", failed.join(", "));
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,36 @@
+//! Tests of the modules of riot-sys' build script
+//!
+//! Build scripts can not have tests of their own, so the modules that contain tests are included
+//! here.
+
+#[path = "../../build/c2rust_passes.rs"]
+#[allow(dead_code)]
+mod c2rust_passes;
+
+#[path = "../../build/c2rust_cache.rs"]
+#[allow(dead_code)]
+mod c2rust_cache;
+
+#[path = "../../build/c2rust_probe.rs"]
+#[allow(dead_code)]
+mod c2rust_probe;
+
+#[path = "../../build/target_check.rs"]
+#[allow(dead_code)]
+mod target_check;
+
+#[path = "../../build/riot_defines.rs"]
+#[allow(dead_code)]
+mod riot_defines;
+
+#[path = "../../build/riot_version.rs"]
+#[allow(dead_code)]
+mod riot_version;
+
+#[path = "../../build/errno.rs"]
+#[allow(dead_code)]
+mod errno;
+
+#[path = "../../build/api_diff.rs"]
+#[allow(dead_code)]
+mod api_diff;